@@ -31,6 +31,7 @@
 
           rust-stable = pkgs'.rust-bin.stable.latest.default.override {
             extensions = [ "rust-src" "clippy" "rustfmt" ];
+            targets = [ "thumbv7em-none-eabihf" ];
           };
 
           rust-nightly = pkgs'.rust-bin.nightly.latest.default.override {
@@ -119,6 +120,14 @@
               cargo clippy --deny
             '';
 
+            # catches `std` creeping into the `no_std` crates
+            thumbv7em = pkgs'.runCommand "cargo-build-thumbv7em" {
+              buildInputs = [ rust-stable ] ++ buildInputs;
+              nativeBuildInputs = nativeBuildInputs;
+            } ''
+              cargo build --release --workspace --no-default-features --target thumbv7em-none-eabihf
+            '';
+
             fmt = pkgs'.runCommand "cargo-fmt" {
               buildInputs = [ rust-stable ];
             } ''
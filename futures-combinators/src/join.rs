@@ -1,22 +1,21 @@
-use crate::wake::WakeArray;
-use futures_compat::LocalWaker;
+use crate::wake::{StoreWaker, WakeArray};
+use core::pin::Pin;
+use core::task::Poll;
 use futures_core::FusedFuture;
 use futures_util::maybe_done::MaybeDone;
 use futures_util::maybe_done::maybe_done;
-use std::pin::Pin;
-use std::task::Poll;
 
 /// from [futures-concurrency](https://github.com/yoshuawuyts/futures-concurrency/tree/main)
 /// Wait for all futures to complete.
 ///
 /// Awaits multiple futures simultaneously, returning the output of the futures
 /// in the same container type they were created once all complete.
-pub trait Join {
+pub trait Join<Waker> {
     /// The resulting output type.
     type Output;
 
     /// The [`ScopedFuture`] implementation returned by this method.
-    type Future: futures_core::Future<LocalWaker, Output = Self::Output>;
+    type Future: futures_core::Future<Waker, Output = Self::Output>;
 
     /// Waits for multiple futures to complete.
     ///
@@ -27,17 +26,20 @@ pub trait Join {
     fn join(self) -> Self::Future;
 }
 
-pub trait JoinExt {
-    fn along_with<Fut>(self, other: Fut) -> Join2<Self, Fut>
+pub trait JoinExt<Waker: StoreWaker> {
+    fn along_with<Fut>(self, other: Fut) -> Join2<Waker, Self, Fut>
     where
-        Self: Sized + futures_core::Future<LocalWaker>,
-        Fut: futures_core::Future<LocalWaker>,
+        Self: Sized + futures_core::Future<Waker>,
+        Fut: futures_core::Future<Waker>,
     {
         (self, other).join()
     }
 }
 
-impl<T> JoinExt for T where T: futures_core::Future<LocalWaker> {}
+impl<Waker: StoreWaker, T> JoinExt<Waker> for T where
+    T: futures_core::Future<Waker>
+{
+}
 
 macro_rules! impl_join_tuple {
     ($namespace:ident $StructName:ident $($F:ident)+) => {
@@ -49,17 +51,17 @@ macro_rules! impl_join_tuple {
 
         #[allow(non_snake_case)]
         #[must_use = "futures do nothing unless you `.await` or poll them"]
-        pub struct $StructName<$($F: futures_core::Future<LocalWaker>),+> {
-            $($F: MaybeDone<$F>,)*
-            wake_array: WakeArray<{$namespace::LEN}>,
+        pub struct $StructName<Waker: StoreWaker, $($F: futures_core::Future<Waker>),+> {
+            $($F: MaybeDone<$F, Waker>,)*
+            wake_array: WakeArray<Waker, {$namespace::LEN}>,
         }
 
-        impl<$($F: futures_core::Future<LocalWaker>),+> futures_core::Future<LocalWaker> for $StructName<$($F),+>
+        impl<Waker: StoreWaker, $($F: futures_core::Future<Waker>),+> futures_core::Future<Waker> for $StructName<Waker, $($F),+>
         {
             type Output = ($($F::Output),+);
 
             #[allow(non_snake_case)]
-            fn poll(self: Pin<&mut Self>, waker: Pin<&LocalWaker>) -> Poll<Self::Output> {
+            fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
                 let this = unsafe { self.get_unchecked_mut() };
 
                 let wake_array = unsafe { Pin::new_unchecked(&this.wake_array) };
@@ -79,7 +81,7 @@ macro_rules! impl_join_tuple {
 
                     // ready if MaybeDone is Done or just completed (converted to Done)
                     // unsafe / against Future api contract to poll after Gone/Future is finished
-                    ready &= if unsafe { dbg!(wake_array.take_woken(index).unwrap_unchecked()) } {
+                    ready &= if unsafe { wake_array.take_woken(index).unwrap_unchecked() } {
                         $F.as_mut().poll(waker).is_ready()
                     } else {
                         $F.is_terminated()
@@ -104,9 +106,9 @@ macro_rules! impl_join_tuple {
             }
         }
 
-        impl<$($F: futures_core::Future<LocalWaker>),+> Join for ($($F),+) {
+        impl<Waker: StoreWaker, $($F: futures_core::Future<Waker>),+> Join<Waker> for ($($F),+) {
             type Output = ($($F::Output),*);
-            type Future = $StructName<$($F),+>;
+            type Future = $StructName<Waker, $($F),+>;
 
             #[allow(non_snake_case)]
             fn join(self) -> Self::Future {
@@ -135,8 +137,6 @@ impl_join_tuple!(join12 Join12 A B C D E F G H I J K L);
 
 #[cfg(test)]
 mod tests {
-    #![no_std]
-
     use futures_core::Future;
     use futures_util::{dummy_guard, poll_fn};
 
@@ -144,7 +144,7 @@ mod tests {
 
     use super::*;
 
-    use std::pin;
+    use core::pin;
 
     #[test]
     fn counters() {
@@ -195,4 +195,61 @@ mod tests {
         let guard = pin::pin!(dummy_guard());
         assert_eq!(join.poll(guard.as_ref()), Poll::Ready((1, 2)));
     }
+
+    #[cfg(feature = "atomics")]
+    #[test]
+    fn atomic_waker() {
+        use futures_util::{AtomicWaker, block_on::block_on};
+
+        let mut x1 = 0;
+        let f1 = poll_fn(|waker: Pin<&AtomicWaker>| {
+            local_wake(waker);
+            x1 += 1;
+            if x1 == 3 {
+                Poll::Ready(x1)
+            } else {
+                Poll::Pending
+            }
+        });
+        let f2 = poll_fn(|_: Pin<&AtomicWaker>| Poll::Ready(2));
+        let join = pin::pin!((f1, f2).join());
+        assert_eq!(block_on(join), (3, 2));
+    }
+
+    #[cfg(feature = "atomics")]
+    #[test]
+    fn woken_from_thread() {
+        extern crate std;
+
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use futures_core::{WakerRegistration, WakerSlot};
+        use futures_util::{AtomicWaker, block_on::block_on};
+        use std::thread;
+
+        let slot = pin::pin!(WakerSlot::<AtomicWaker>::new());
+        let slot = slot.into_ref();
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let mut spawned = false;
+            let f1 = poll_fn(|waker: Pin<&AtomicWaker>| {
+                if done.load(Ordering::Acquire) {
+                    return Poll::Ready(1);
+                }
+                WakerRegistration::new(waker).register(slot);
+                if !spawned {
+                    spawned = true;
+                    let done = &done;
+                    scope.spawn(move || {
+                        done.store(true, Ordering::Release);
+                        slot.wake();
+                    });
+                }
+                Poll::Pending
+            });
+            let f2 = poll_fn(|_: Pin<&AtomicWaker>| Poll::Ready(2));
+            let join = pin::pin!((f1, f2).join());
+            assert_eq!(block_on(join), (1, 2));
+        });
+    }
 }
@@ -1,6 +1,9 @@
+#![no_std]
+
 pub mod join;
 pub mod race;
 mod wake;
 
-use join::*;
-use race::*;
+pub use join::*;
+pub use race::*;
+pub use wake::StoreWaker;
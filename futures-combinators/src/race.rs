@@ -1,20 +1,18 @@
-use futures_util::LocalWaker;
-
-use crate::wake::WakeArray;
-use std::pin::Pin;
-use std::task::Poll;
+use crate::wake::{StoreWaker, WakeArray};
+use core::pin::Pin;
+use core::task::Poll;
 
 /// from [futures-concurrency](https://github.com/yoshuawuyts/futures-concurrency/tree/main)
 /// Wait for the first future to complete.
 ///
 /// Awaits multiple future at once, returning as soon as one completes. The
 /// other futures are cancelled.
-pub trait Race {
+pub trait Race<Waker> {
     /// The resulting output type.
     type Output;
 
     /// The [`ScopedFuture`] implementation returned by this method.
-    type Future: futures_core::Future<LocalWaker, Output = Self::Output>;
+    type Future: futures_core::Future<Waker, Output = Self::Output>;
 
     /// Wait for the first future to complete.
     ///
@@ -25,17 +23,20 @@ pub trait Race {
     fn race(self) -> Self::Future;
 }
 
-pub trait RaceExt<'scope> {
-    fn race_with<Fut>(self, other: Fut) -> Race2<Self, Fut>
+pub trait RaceExt<'scope, Waker: StoreWaker> {
+    fn race_with<Fut>(self, other: Fut) -> Race2<Waker, Self, Fut>
     where
-        Self: Sized + futures_core::Future<LocalWaker>,
-        Fut: futures_core::Future<LocalWaker>,
+        Self: Sized + futures_core::Future<Waker>,
+        Fut: futures_core::Future<Waker>,
     {
         (self, other).race()
     }
 }
 
-impl<'scope, T> RaceExt<'scope> for T where T: futures_core::Future<LocalWaker> {}
+impl<'scope, Waker: StoreWaker, T> RaceExt<'scope, Waker> for T where
+    T: futures_core::Future<Waker>
+{
+}
 
 macro_rules! impl_race_tuple {
     ($namespace:ident $StructName:ident $OutputsName:ident $($F:ident)+) => {
@@ -49,11 +50,11 @@ macro_rules! impl_race_tuple {
             $($F($F),)+
         }
 
-        impl<$($F: std::fmt::Debug,)+> std::fmt::Debug for $OutputsName<$($F,)+> {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        impl<$($F: core::fmt::Debug,)+> core::fmt::Debug for $OutputsName<$($F,)+> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
                 match self {$(
                     Self::$F(x) =>
-                    f.debug_tuple(std::stringify!($F))
+                    f.debug_tuple(core::stringify!($F))
                         .field(x)
                         .finish(),
                 )+}
@@ -71,18 +72,18 @@ macro_rules! impl_race_tuple {
 
         #[allow(non_snake_case)]
         #[must_use = "futures do nothing unless you `.await` or poll them"]
-        pub struct $StructName<$($F: futures_core::Future<LocalWaker>),+> {
+        pub struct $StructName<Waker: StoreWaker, $($F: futures_core::Future<Waker>),+> {
             $($F: $F,)*
-            wake_array: WakeArray<{$namespace::LEN}>,
+            wake_array: WakeArray<Waker, {$namespace::LEN}>,
         }
 
-        impl<'scope, $($F: futures_core::Future<LocalWaker>),+> futures_core::Future<LocalWaker>
-            for $StructName<$($F),+>
+        impl<'scope, Waker: StoreWaker, $($F: futures_core::Future<Waker>),+> futures_core::Future<Waker>
+            for $StructName<Waker, $($F),+>
         {
             type Output = $OutputsName<$($F::Output,)+>;
 
             #[allow(non_snake_case)]
-            fn poll(self: Pin<&mut Self>, waker: Pin<&LocalWaker>) -> Poll<Self::Output> {
+            fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
                 let this = unsafe { self.get_unchecked_mut() };
 
                 let wake_array = unsafe { Pin::new_unchecked(&this.wake_array) };
@@ -108,9 +109,9 @@ macro_rules! impl_race_tuple {
             }
         }
 
-        impl<'scope, $($F: futures_core::Future<LocalWaker>),+> Race for ($($F),+) {
+        impl<'scope, Waker: StoreWaker, $($F: futures_core::Future<Waker>),+> Race<Waker> for ($($F),+) {
             type Output = $OutputsName<$($F::Output),*>;
-            type Future = $StructName<$($F),+>;
+            type Future = $StructName<Waker, $($F),+>;
 
             #[allow(non_snake_case)]
             fn race(self) -> Self::Future {
@@ -139,9 +140,7 @@ impl_race_tuple!(race12 Race12 RaceOutputs12 A B C D E F G H I J K L);
 
 #[cfg(test)]
 mod tests {
-    #![no_std]
-
-    use std::pin;
+    use core::pin;
 
     use futures_core::Future;
     use futures_util::{dummy_guard, poll_fn};
@@ -197,4 +196,24 @@ mod tests {
         let guard = pin::pin!(dummy_guard());
         assert_eq!(race.poll(guard.as_ref()), Poll::Ready(RaceOutputs2::A(1)));
     }
+
+    #[cfg(feature = "atomics")]
+    #[test]
+    fn atomic_waker() {
+        use futures_util::{AtomicWaker, block_on::block_on};
+
+        let mut x1 = 0;
+        let f1 = poll_fn(|waker: Pin<&AtomicWaker>| {
+            local_wake(waker);
+            x1 += 1;
+            if x1 == 3 {
+                Poll::Ready(x1)
+            } else {
+                Poll::Pending
+            }
+        });
+        let f2 = poll_fn(|_: Pin<&AtomicWaker>| Poll::<i32>::Pending);
+        let race = pin::pin!((f1, f2).race());
+        assert_eq!(block_on(race), RaceOutputs2::A(3));
+    }
 }
@@ -1,107 +1,182 @@
-use std::{array, cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull};
-
-use futures_compat::{LocalWaker, WakePtr};
-use futures_core::Wake;
-use lifetime_guard::{guard::RefGuard, guard::ValueGuard};
+use core::{array, cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull};
+
+use futures_core::{Wake, WakeGuard, WakePtr};
+use lifetime_guard::guard::{RefGuard, ValueGuard};
+
+/// [`WakeGuard`] that can be used as the waker of `Join` and `Race`.
+///
+/// Each child guard they hand out points to a [`Store`](Self::Store), which
+/// records that the child was woken and wakes the parent task. The store
+/// has to be as thread safe as the guard, so every guard provides its own.
+pub trait StoreWaker: WakeGuard {
+    /// Records whether a child guard was woken, and wakes the parent task.
+    type Store: Default;
+
+    /// Points `store` to the `parent` it wakes.
+    fn set_parent(store: &Self::Store, parent: &Self::Parent);
+
+    /// Returns `true` if `store` was woken since this was last called.
+    fn take_woken(store: &Self::Store) -> bool;
+
+    /// Returns a pointer that wakes `store`, for its child guard.
+    ///
+    /// # Safety
+    ///
+    /// `store` must outlive every guard the pointer is stored in, and its
+    /// parent must outlive `store`.
+    unsafe fn store_ptr(store: &Self::Store) -> Self::Ptr;
+}
 
-pub struct WakeArray<const N: usize> {
-    parent: RefGuard<WakePtr>,
-    children: [ValueGuard<WakePtr>; N],
-    stores: [WakeStore; N],
+pub struct WakeArray<Waker: StoreWaker, const N: usize> {
+    // children are dropped first, so nothing can wake the stores once they
+    // and the parent are dropped
+    children: [Waker; N],
+    stores: [Waker::Store; N],
+    parent: Waker::Parent,
     _marker: PhantomPinned,
 }
 
-impl<const N: usize> WakeArray<N> {
+impl<Waker: StoreWaker, const N: usize> WakeArray<Waker, N> {
     pub fn new() -> Self {
         Self {
-            parent: RefGuard::new(),
-            children: array::from_fn(|_| ValueGuard::new(None)),
-            stores: array::from_fn(|_| WakeStore::new()),
+            children: array::from_fn(|_| Waker::new(Waker::Ptr::default())),
+            stores: array::from_fn(|_| Waker::Store::default()),
+            parent: Waker::Parent::default(),
             _marker: PhantomPinned,
         }
     }
 
-    pub fn register_parent(
-        self: Pin<&Self>,
-        parent: Pin<&ValueGuard<WakePtr>>,
-    ) {
-        unsafe { Pin::new_unchecked(&self.parent) }.register(parent);
+    pub fn register_parent(self: Pin<&Self>, parent: Pin<&Waker>) {
+        parent.register_parent(unsafe { Pin::new_unchecked(&self.parent) });
     }
 
-    /// Returns pinned reference to child ValueGuard
+    /// Returns pinned reference to child guard
     /// returns None if n is not in 0..N
     pub fn child_guard_ptr(
         self: Pin<&Self>,
         index: usize,
-    ) -> Option<Pin<&ValueGuard<WakePtr>>> {
+    ) -> Option<Pin<&Waker>> {
         // TODO remove bounds checking, break api when https://github.com/rust-lang/rust/issues/123646
         if index >= N {
             return None;
         }
 
         let wake_store = unsafe { self.stores.get(index).unwrap_unchecked() };
-        wake_store.set_parent(&self.parent);
-
-        let wake_store = unsafe {
-            NonNull::new_unchecked(
-                wake_store as *const dyn Wake as *mut dyn Wake,
-            )
-        };
+        Waker::set_parent(wake_store, &self.parent);
 
         let child_guard =
             unsafe { self.get_ref().children.get(index).unwrap_unchecked() };
-        child_guard.set(Some(wake_store));
+        // `self` is pinned, and its children are dropped before its stores
+        child_guard.set(unsafe { Waker::store_ptr(wake_store) });
 
         Some(unsafe { Pin::new_unchecked(child_guard) })
     }
 
     pub fn take_woken(self: Pin<&Self>, index: usize) -> Option<bool> {
-        self.stores.get(index).map(|store| store.take_woken())
+        self.stores.get(index).map(Waker::take_woken)
     }
 }
 
-pub struct WakeStore {
+/// [`StoreWaker::Store`] of `ValueGuard<WakePtr>`.
+pub struct LocalStore {
     wake_parent: Cell<Option<NonNull<RefGuard<WakePtr>>>>,
     activated: Cell<bool>,
 }
 
-impl WakeStore {
-    pub fn new() -> Self {
+impl Default for LocalStore {
+    fn default() -> Self {
         Self {
             wake_parent: Cell::new(None),
             activated: Cell::new(true),
         }
     }
+}
 
-    pub fn set_parent(&self, parent: &RefGuard<WakePtr>) {
-        self.wake_parent.set(Some(parent.into()));
+impl Wake for LocalStore {
+    fn wake(&self) {
+        self.activated.set(true);
+        if let Some(parent) = self.wake_parent.get() {
+            <ValueGuard<WakePtr>>::wake_parent(unsafe { parent.as_ref() });
+        }
+    }
+}
+
+impl StoreWaker for ValueGuard<WakePtr> {
+    type Store = LocalStore;
+
+    fn set_parent(store: &LocalStore, parent: &Self::Parent) {
+        store.wake_parent.set(Some(parent.into()));
     }
 
-    pub fn take_woken(&self) -> bool {
-        self.activated.replace(false)
+    fn take_woken(store: &LocalStore) -> bool {
+        store.activated.replace(false)
+    }
+
+    unsafe fn store_ptr(store: &LocalStore) -> WakePtr {
+        unsafe { WakePtr::new(NonNull::from(store)) }
     }
 }
 
-impl Wake for WakeStore {
-    fn wake(&self) {
-        dbg!("awake?");
-        self.activated.set(true);
-        if let Some(parent) = self
-            .wake_parent
-            .get()
-            .map(|guard_ptr| unsafe { &*guard_ptr.as_ptr() })
-            .and_then(|guard| guard.get())
-            .flatten()
-        {
-            unsafe { &*parent.as_ptr() }.wake();
+#[cfg(feature = "atomics")]
+mod atomic {
+    use core::{
+        ptr::{self, NonNull},
+        sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+    };
+
+    use futures_core::{SyncWakePtr, Wake, WakeGuard};
+    use lifetime_guard::atomic_guard::{AtomicRefGuard, AtomicValueGuard};
+
+    use super::StoreWaker;
+
+    /// [`StoreWaker::Store`] of `AtomicValueGuard<SyncWakePtr>`, which can be
+    /// woken from any thread.
+    pub struct AtomicStore {
+        wake_parent: AtomicPtr<AtomicRefGuard<SyncWakePtr>>,
+        activated: AtomicBool,
+    }
+
+    impl Default for AtomicStore {
+        fn default() -> Self {
+            Self {
+                wake_parent: AtomicPtr::new(ptr::null_mut()),
+                activated: AtomicBool::new(true),
+            }
         }
     }
-}
 
-pub fn local_wake(guard: &LocalWaker) {
-    if let Some(wake) = guard.get() {
-        unsafe { (*wake.as_ptr()).wake() }
+    impl Wake for AtomicStore {
+        fn wake(&self) {
+            self.activated.store(true, Ordering::Release);
+            let parent = self.wake_parent.load(Ordering::Acquire);
+            if let Some(parent) = unsafe { parent.as_ref() } {
+                <AtomicValueGuard<SyncWakePtr>>::wake_parent(parent);
+            }
+        }
     }
+
+    impl StoreWaker for AtomicValueGuard<SyncWakePtr> {
+        type Store = AtomicStore;
+
+        fn set_parent(store: &AtomicStore, parent: &Self::Parent) {
+            store
+                .wake_parent
+                .store(ptr::from_ref(parent).cast_mut(), Ordering::Release);
+        }
+
+        fn take_woken(store: &AtomicStore) -> bool {
+            store.activated.swap(false, Ordering::Acquire)
+        }
+
+        unsafe fn store_ptr(store: &AtomicStore) -> SyncWakePtr {
+            unsafe { SyncWakePtr::new(NonNull::from(store)) }
+        }
+    }
+}
+
+#[cfg(test)]
+pub fn local_wake(guard: Pin<&impl WakeGuard>) {
+    guard.wake();
 }
 
 // pub unsafe fn wake_bespoke_waker(waker: &std::task::Waker) {
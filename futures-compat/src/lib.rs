@@ -1,20 +1,23 @@
 //! Any interaction between an executor/reactor intended for task::Future
 //! with an executor/reactor intended for bcsc::Future is strictly unsound.
 
-use std::{
+#![no_std]
+
+use core::{
     hint::unreachable_unchecked,
     mem::ManuallyDrop,
     pin::Pin,
-    ptr::NonNull,
     task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
 };
 
-use futures_core::Wake;
-use lifetime_guard::{atomic_guard::AtomicValueGuard, guard::ValueGuard};
+#[cfg(feature = "atomics")]
+use lifetime_guard::atomic_guard::AtomicValueGuard;
+use lifetime_guard::guard::ValueGuard;
 
-pub type WakePtr = Option<NonNull<dyn Wake>>;
+pub use futures_core::{SyncWakePtr, WakePtr};
 pub type LocalWaker = ValueGuard<WakePtr>;
-pub type AtomicWaker = AtomicValueGuard<WakePtr>;
+#[cfg(feature = "atomics")]
+pub type AtomicWaker = AtomicValueGuard<SyncWakePtr>;
 
 static EVIL_VTABLE: RawWakerVTable = unsafe {
     RawWakerVTable::new(
@@ -29,6 +32,12 @@ static EVIL_VTABLE: RawWakerVTable = unsafe {
 /// `core::future::Future`
 ///
 /// Any usage or storage of the resulting `Waker` is undefined behavior.
+///
+/// # Safety
+///
+/// The resulting `Waker` must only be passed to a `core::future::Future`
+/// that converts it back using [`waker_to_guard`] and must never be woken,
+/// cloned, or dropped.
 pub unsafe fn guard_to_waker(guard: Pin<&LocalWaker>) -> ManuallyDrop<Waker> {
     ManuallyDrop::new(unsafe {
         Waker::from_raw(RawWaker::new(
@@ -38,12 +47,19 @@ pub unsafe fn guard_to_waker(guard: Pin<&LocalWaker>) -> ManuallyDrop<Waker> {
     })
 }
 
+/// Coerces a pinned `AtomicValueGuard` reference to a `Waker` for use in
+/// `core::future::Future`
+///
+/// # Safety
+///
+/// See [`guard_to_waker`].
+#[cfg(feature = "atomics")]
 pub unsafe fn atomic_guard_to_waker(
     guard: Pin<&AtomicWaker>,
 ) -> ManuallyDrop<Waker> {
     ManuallyDrop::new(unsafe {
         Waker::from_raw(RawWaker::new(
-            guard.get_ref() as *const AtomicValueGuard<WakePtr> as *const (),
+            guard.get_ref() as *const AtomicWaker as *const (),
             &EVIL_VTABLE,
         ))
     })
@@ -52,24 +68,45 @@ pub unsafe fn atomic_guard_to_waker(
 /// Coerces a `Waker` into a pinned `AtomicValueGuard` reference.
 ///
 /// This should only be used to undo the work of `guard_to_waker`.
-pub unsafe fn waker_to_guard<'a>(waker: &Waker) -> Pin<&LocalWaker> {
+///
+/// # Safety
+///
+/// `waker` must have been created by [`guard_to_waker`] from a guard that is
+/// still alive.
+pub unsafe fn waker_to_guard(waker: &Waker) -> Pin<&LocalWaker> {
     unsafe {
         Pin::new_unchecked(&*(waker.data() as *const ValueGuard<WakePtr>))
     }
 }
 
-pub unsafe fn waker_to_atomic_guard<'a>(waker: &Waker) -> Pin<&AtomicWaker> {
-    unsafe {
-        Pin::new_unchecked(&*(waker.data() as *const AtomicValueGuard<WakePtr>))
-    }
+/// Coerces a `Waker` into a pinned `AtomicValueGuard` reference.
+///
+/// # Safety
+///
+/// `waker` must have been created by [`atomic_guard_to_waker`] from a guard
+/// that is still alive.
+#[cfg(feature = "atomics")]
+pub unsafe fn waker_to_atomic_guard(waker: &Waker) -> Pin<&AtomicWaker> {
+    unsafe { Pin::new_unchecked(&*(waker.data() as *const AtomicWaker)) }
 }
 
+/// Wraps a `core::future::Future` so it can be polled as a `bcsc::Future`.
+///
+/// # Safety
+///
+/// `future` must not store, clone, or wake the `Waker` it is polled with.
 pub unsafe fn std_future_to_bespoke<F: core::future::Future>(
     future: F,
 ) -> impl futures_core::Future<LocalWaker, Output = F::Output> {
     NormalFutureWrapper(future)
 }
 
+/// Wraps a `bcsc::Future` so it can be polled as a `core::future::Future`.
+///
+/// # Safety
+///
+/// The resulting future must only be polled with a `Waker` created by
+/// [`guard_to_waker`].
 pub unsafe fn bespoke_future_to_std<F: futures_core::Future<LocalWaker>>(
     future: F,
 ) -> impl core::future::Future<Output = F::Output> {
@@ -118,7 +155,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use std::pin;
+    use core::{pin, ptr::NonNull};
 
     use super::*;
     use futures_core::Wake;
@@ -132,13 +169,13 @@ mod test {
     #[test]
     fn waker_conversion() {
         let dummy = DummyWake;
-        let guard = pin::pin!(ValueGuard::new(NonNull::new(
-            &dummy as *const dyn Wake as *mut dyn Wake
-        )));
+        let guard = pin::pin!(ValueGuard::new(unsafe {
+            WakePtr::new(NonNull::from(&dummy))
+        }));
         let waker = unsafe { guard_to_waker(guard.as_ref()) };
         let guard = unsafe { waker_to_guard(&waker) };
         assert_eq!(
-            guard.get().unwrap().as_ptr() as *const () as usize,
+            guard.get().as_ptr().unwrap().as_ptr() as *const () as usize,
             &dummy as *const _ as *const () as usize
         );
     }
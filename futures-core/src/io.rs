@@ -0,0 +1,279 @@
+//! Asynchronous byte IO traits, redefined for the bespoke waker.
+//!
+//! Unlike the `futures-io` traits, these don't depend on `std::io`, so each
+//! implementation chooses its own error type.
+
+use core::{convert::Infallible, ops, pin::Pin, task::Poll};
+
+/// Read bytes asynchronously.
+///
+/// This trait is analogous to the `std::io::Read` trait, but integrates
+/// with the asynchronous task system. In particular, the `poll_read`
+/// method, unlike `Read::read`, will automatically queue the current task
+/// for wakeup and return if data is not yet available, rather than blocking
+/// the calling thread.
+pub trait AsyncRead<Waker> {
+    /// The error produced when reading fails.
+    type Error;
+
+    /// Attempt to read from the `AsyncRead` into `buf`.
+    ///
+    /// On success, returns `Poll::Ready(Ok(num_bytes_read))`, where a
+    /// `num_bytes_read` of `0` means the end of the stream was reached,
+    /// unless `buf` is empty.
+    ///
+    /// If no data is available for reading, the method returns
+    /// `Poll::Pending` and arranges for the current task to be woken when
+    /// the object becomes readable or is closed.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, Self::Error>>;
+}
+
+/// Write bytes asynchronously.
+///
+/// This trait is analogous to the `std::io::Write` trait, but integrates
+/// with the asynchronous task system. In particular, the `poll_write`
+/// method, unlike `Write::write`, will automatically queue the current task
+/// for wakeup and return if the writer cannot take more data, rather than
+/// blocking the calling thread.
+pub trait AsyncWrite<Waker> {
+    /// The error produced when writing fails.
+    type Error;
+
+    /// Attempt to write bytes from `buf` into the object.
+    ///
+    /// On success, returns `Poll::Ready(Ok(num_bytes_written))`, where a
+    /// `num_bytes_written` of `0` means the object can't accept any more
+    /// bytes, unless `buf` is empty.
+    ///
+    /// If the object is not ready for writing, the method returns
+    /// `Poll::Pending` and arranges for the current task to be woken when
+    /// the object becomes writable or is closed.
+    fn poll_write(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Self::Error>>;
+
+    /// Attempt to flush the object, ensuring that any buffered data reach
+    /// their destination.
+    ///
+    /// On success, returns `Poll::Ready(Ok(()))`.
+    ///
+    /// If flushing cannot immediately complete, this method returns
+    /// `Poll::Pending` and arranges for the current task to be woken when
+    /// the object can make progress towards flushing.
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>>;
+
+    /// Attempt to close the object.
+    ///
+    /// On success, returns `Poll::Ready(Ok(()))`.
+    ///
+    /// If closing cannot immediately complete, this function returns
+    /// `Poll::Pending` and arranges for the current task to be woken when
+    /// the object can make progress towards closing.
+    fn poll_close(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>>;
+}
+
+/// Read bytes asynchronously through an internal buffer.
+///
+/// This trait is analogous to the `std::io::BufRead` trait, but integrates
+/// with the asynchronous task system. In particular, the `poll_fill_buf`
+/// method, unlike `BufRead::fill_buf`, will automatically queue the current
+/// task for wakeup and return if data is not yet available, rather than
+/// blocking the calling thread.
+pub trait AsyncBufRead<Waker>: AsyncRead<Waker> {
+    /// Attempt to return the contents of the internal buffer, filling it
+    /// with more data from the inner reader if it is empty.
+    ///
+    /// On success, returns `Poll::Ready(Ok(buf))`, where an empty `buf`
+    /// means the end of the stream was reached.
+    ///
+    /// If no data is available for reading, the method returns
+    /// `Poll::Pending` and arranges for the current task to be woken when
+    /// the object becomes readable or is closed.
+    ///
+    /// This function is a lower-level call. It needs to be paired with the
+    /// [`consume`] method to function properly.
+    ///
+    /// [`consume`]: AsyncBufRead::consume
+    fn poll_fill_buf<'a>(
+        self: Pin<&'a mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<&'a [u8], Self::Error>>;
+
+    /// Tells this buffer that `amt` bytes have been consumed from the
+    /// buffer, so they should no longer be returned in calls to
+    /// [`poll_fill_buf`].
+    ///
+    /// The `amt` must be `<=` the number of bytes in the buffer returned by
+    /// [`poll_fill_buf`].
+    ///
+    /// [`poll_fill_buf`]: AsyncBufRead::poll_fill_buf
+    fn consume(self: Pin<&mut Self>, amt: usize);
+}
+
+impl<Waker, R: ?Sized + AsyncRead<Waker> + Unpin> AsyncRead<Waker> for &mut R {
+    type Error = R::Error;
+
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, Self::Error>> {
+        R::poll_read(Pin::new(&mut **self), waker, buf)
+    }
+}
+
+impl<Waker, P> AsyncRead<Waker> for Pin<P>
+where
+    P: ops::DerefMut<Target: AsyncRead<Waker>>,
+{
+    type Error = <P::Target as AsyncRead<Waker>>::Error;
+
+    fn poll_read(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, Self::Error>> {
+        <P::Target as AsyncRead<Waker>>::poll_read(
+            self.as_deref_mut(),
+            waker,
+            buf,
+        )
+    }
+}
+
+impl<Waker, W: ?Sized + AsyncWrite<Waker> + Unpin> AsyncWrite<Waker>
+    for &mut W
+{
+    type Error = W::Error;
+
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Self::Error>> {
+        W::poll_write(Pin::new(&mut **self), waker, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        W::poll_flush(Pin::new(&mut **self), waker)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        W::poll_close(Pin::new(&mut **self), waker)
+    }
+}
+
+impl<Waker, P> AsyncWrite<Waker> for Pin<P>
+where
+    P: ops::DerefMut<Target: AsyncWrite<Waker>>,
+{
+    type Error = <P::Target as AsyncWrite<Waker>>::Error;
+
+    fn poll_write(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, Self::Error>> {
+        <P::Target as AsyncWrite<Waker>>::poll_write(
+            self.as_deref_mut(),
+            waker,
+            buf,
+        )
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        <P::Target as AsyncWrite<Waker>>::poll_flush(self.as_deref_mut(), waker)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        <P::Target as AsyncWrite<Waker>>::poll_close(self.as_deref_mut(), waker)
+    }
+}
+
+impl<Waker, R: ?Sized + AsyncBufRead<Waker> + Unpin> AsyncBufRead<Waker>
+    for &mut R
+{
+    fn poll_fill_buf<'a>(
+        self: Pin<&'a mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<&'a [u8], Self::Error>> {
+        R::poll_fill_buf(Pin::new(&mut **self.get_mut()), waker)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        R::consume(Pin::new(&mut **self), amt)
+    }
+}
+
+impl<Waker, P> AsyncBufRead<Waker> for Pin<P>
+where
+    P: ops::DerefMut<Target: AsyncBufRead<Waker>>,
+{
+    fn poll_fill_buf<'a>(
+        self: Pin<&'a mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<&'a [u8], Self::Error>> {
+        <P::Target as AsyncBufRead<Waker>>::poll_fill_buf(
+            self.as_deref_mut(),
+            waker,
+        )
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        <P::Target as AsyncBufRead<Waker>>::consume(self.as_deref_mut(), amt)
+    }
+}
+
+/// Reads from the front of the slice, which is advanced past the bytes read.
+impl<Waker> AsyncRead<Waker> for &[u8] {
+    type Error = Infallible;
+
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _waker: Pin<&Waker>,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, Self::Error>> {
+        let amt = buf.len().min(self.len());
+        let (read, rest) = self.split_at(amt);
+        buf[..amt].copy_from_slice(read);
+        *self = rest;
+        Poll::Ready(Ok(amt))
+    }
+}
+
+impl<Waker> AsyncBufRead<Waker> for &[u8] {
+    fn poll_fill_buf<'a>(
+        self: Pin<&'a mut Self>,
+        _waker: Pin<&Waker>,
+    ) -> Poll<Result<&'a [u8], Self::Error>> {
+        Poll::Ready(Ok(*self.get_mut()))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        *self = &self[amt..];
+    }
+}
@@ -1,11 +1,26 @@
 //! Redefinitions of task::Future to be incompatible with them
 
-use std::{
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+use core::{
     ops::{self, DerefMut},
     pin::Pin,
+    ptr::NonNull,
     task::Poll,
 };
 
+pub mod io;
+pub mod registration;
+#[cfg(feature = "test-util")]
+#[doc(hidden)]
+pub mod test_util;
+
+pub use io::{AsyncBufRead, AsyncRead, AsyncWrite};
+pub use registration::{WakerRegistration, WakerSlot};
+
 /// A future represents an asynchronous computation obtained by use of `async`.
 ///
 /// This future assumes a nonstandard Context, which is incompatible with
@@ -113,6 +128,20 @@ impl<Waker, F: ?Sized + Future<Waker> + Unpin> Future<Waker> for &mut F {
     }
 }
 
+#[cfg(feature = "std")]
+impl<Waker, F: ?Sized + Future<Waker> + Unpin> Future<Waker>
+    for std::boxed::Box<F>
+{
+    type Output = F::Output;
+
+    fn poll(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Self::Output> {
+        F::poll(Pin::new(&mut **self), waker)
+    }
+}
+
 impl<Waker, P> Future<Waker> for Pin<P>
 where
     P: ops::DerefMut<Target: Future<Waker>>,
@@ -155,7 +184,565 @@ where
     }
 }
 
+mod private_try_future {
+    use super::Future;
+
+    pub trait Sealed<Waker> {}
+
+    impl<Waker, F, T, E> Sealed<Waker> for F where
+        F: ?Sized + Future<Waker, Output = Result<T, E>>
+    {
+    }
+}
+
+/// A convenience for futures that return `Result` values that includes
+/// a variety of adapters tailored to such futures.
+pub trait TryFuture<Waker>:
+    Future<Waker> + private_try_future::Sealed<Waker>
+{
+    /// The type of successful values yielded by this future
+    type Ok;
+
+    /// The type of failures yielded by this future
+    type Error;
+
+    /// Poll this `TryFuture` as if it were a `Future`.
+    ///
+    /// This method is a stopgap for a compiler limitation that prevents us
+    /// from directly inheriting from the `Future` trait; in the future it
+    /// won't be needed.
+    fn try_poll(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<Self::Ok, Self::Error>>;
+}
+
+impl<Waker, F, T, E> TryFuture<Waker> for F
+where
+    F: ?Sized + Future<Waker, Output = Result<T, E>>,
+{
+    type Ok = T;
+    type Error = E;
+
+    #[inline]
+    fn try_poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<F::Output> {
+        self.poll(waker)
+    }
+}
+
+/// A stream of values produced asynchronously.
+///
+/// Like [`Future`], this assumes a nonstandard waker, and is incompatible with
+/// `futures_core::Stream` from the `futures` crate.
+///
+/// If `Future<Output = T>` is an asynchronous version of `T`, then
+/// `Stream<Item = T>` is an asynchronous version of `Iterator<Item = T>`. A
+/// stream represents a sequence of value-producing events that occur
+/// asynchronously to the caller.
+///
+/// The trait is modeled after `Future`, but allows `poll_next` to be called
+/// even after a value has been produced, yielding `None` once the stream has
+/// been fully exhausted.
+#[must_use = "streams do nothing unless polled"]
+pub trait Stream<Waker> {
+    /// Values yielded by the stream.
+    type Item;
+
+    /// Attempt to pull out the next value of this stream, registering the
+    /// current task for wakeup if the value is not yet available, and
+    /// returning `None` if the stream is exhausted.
+    ///
+    /// # Return value
+    ///
+    /// There are several possible return values, each indicating a distinct
+    /// stream state:
+    ///
+    /// - `Poll::Pending` means that this stream's next value is not ready
+    ///   yet. Implementations will ensure that the current task will be
+    ///   notified when the next value may be ready.
+    ///
+    /// - `Poll::Ready(Some(val))` means that the stream has successfully
+    ///   produced a value, `val`, and may produce further values on
+    ///   subsequent `poll_next` calls.
+    ///
+    /// - `Poll::Ready(None)` means that the stream has terminated, and
+    ///   `poll_next` should not be invoked again.
+    ///
+    /// # Panics
+    ///
+    /// Once a stream has finished (returned `Ready(None)` from `poll_next`),
+    /// calling its `poll_next` method again may panic, block forever, or
+    /// cause other kinds of problems; the `Stream` trait places no
+    /// requirements on the effects of such a call. However, as the
+    /// `poll_next` method is not marked `unsafe`, Rust's usual rules apply:
+    /// calls must never cause undefined behavior (memory corruption,
+    /// incorrect use of `unsafe` functions, or the like), regardless of the
+    /// stream's state.
+    ///
+    /// If this is difficult to guard against then the [`FusedStream`] trait
+    /// can be used to determine whether the stream has terminated.
+    fn poll_next(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Self::Item>>;
+
+    /// Returns the bounds on the remaining length of the stream.
+    ///
+    /// Specifically, `size_hint()` returns a tuple where the first element
+    /// is the lower bound, and the second element is the upper bound.
+    ///
+    /// The second half of the tuple that is returned is an
+    /// [`Option`]`<`[`usize`]`>`. A [`None`] here means that either there
+    /// is no known upper bound, or the upper bound is larger than
+    /// [`usize`].
+    ///
+    /// `size_hint()` is primarily intended to be used for optimizations such
+    /// as reserving space for the elements of the stream, but must not be
+    /// trusted to e.g., omit bounds checks in unsafe code. An incorrect
+    /// implementation of `size_hint()` should not lead to memory safety
+    /// violations.
+    ///
+    /// The default implementation returns `(0, `[`None`]`)` which is correct
+    /// for any stream.
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, None)
+    }
+}
+
+impl<Waker, S: ?Sized + Stream<Waker> + Unpin> Stream<Waker> for &mut S {
+    type Item = S::Item;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Self::Item>> {
+        S::poll_next(Pin::new(&mut **self), waker)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Waker, S: ?Sized + Stream<Waker> + Unpin> Stream<Waker>
+    for std::boxed::Box<S>
+{
+    type Item = S::Item;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Self::Item>> {
+        S::poll_next(Pin::new(&mut **self), waker)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+impl<Waker, P> Stream<Waker> for Pin<P>
+where
+    P: ops::DerefMut<Target: Stream<Waker>>,
+{
+    type Item = <<P as ops::Deref>::Target as Stream<Waker>>::Item;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Self::Item>> {
+        <P::Target as Stream<Waker>>::poll_next(self.as_deref_mut(), waker)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (**self).size_hint()
+    }
+}
+
+/// A stream which tracks whether or not the underlying stream
+/// should no longer be polled.
+///
+/// `is_terminated` will return `true` if a stream should no longer be polled.
+/// Usually, this state occurs after `poll_next` returned
+/// `Poll::Ready(None)`. However, `is_terminated` may also return `true` if a
+/// stream has become inactive and can no longer make progress and should be
+/// ignored or dropped rather than being `poll`ed again.
+pub trait FusedStream<Waker>: Stream<Waker> {
+    /// Returns `true` if the stream should no longer be polled.
+    fn is_terminated(&self) -> bool;
+}
+
+impl<Waker, F: ?Sized + FusedStream<Waker> + Unpin> FusedStream<Waker>
+    for &mut F
+{
+    fn is_terminated(&self) -> bool {
+        <F as FusedStream<Waker>>::is_terminated(&**self)
+    }
+}
+
+impl<Waker, P> FusedStream<Waker> for Pin<P>
+where
+    P: DerefMut + Unpin,
+    P::Target: FusedStream<Waker>,
+{
+    fn is_terminated(&self) -> bool {
+        <P::Target as FusedStream<Waker>>::is_terminated(&**self)
+    }
+}
+
+mod private_try_stream {
+    use super::Stream;
+
+    pub trait Sealed<Waker> {}
+
+    impl<Waker, S, T, E> Sealed<Waker> for S where
+        S: ?Sized + Stream<Waker, Item = Result<T, E>>
+    {
+    }
+}
+
+/// A convenience for streams that return `Result` values that includes
+/// a variety of adapters tailored to such futures.
+pub trait TryStream<Waker>:
+    Stream<Waker> + private_try_stream::Sealed<Waker>
+{
+    /// The type of successful values yielded by this future
+    type Ok;
+
+    /// The type of failures yielded by this future
+    type Error;
+
+    /// Poll this `TryStream` as if it were a `Stream`.
+    ///
+    /// This method is a stopgap for a compiler limitation that prevents us
+    /// from directly inheriting from the `Stream` trait; in the future it
+    /// won't be needed.
+    fn try_poll_next(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Result<Self::Ok, Self::Error>>>;
+}
+
+impl<Waker, S, T, E> TryStream<Waker> for S
+where
+    S: ?Sized + Stream<Waker, Item = Result<T, E>>,
+{
+    type Ok = T;
+    type Error = E;
+
+    #[inline]
+    fn try_poll_next(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Result<Self::Ok, Self::Error>>> {
+        self.poll_next(waker)
+    }
+}
+
+/// A `Sink` is a value into which other values can be sent, asynchronously.
+///
+/// Like [`Future`], this assumes a nonstandard waker, and is incompatible with
+/// `futures_sink::Sink` from the `futures` crate.
+///
+/// Values are sent in two phases: first, the sink must be ready to accept a
+/// value, as signaled by [`poll_ready`](Sink::poll_ready), after which the
+/// value is handed over with [`start_send`](Sink::start_send). Sinks may
+/// buffer the values they are sent, so [`poll_flush`](Sink::poll_flush) must
+/// be called to ensure they have actually been processed.
+///
+/// This gives consumers backpressure: a slow sink simply isn't ready until
+/// it can accept another value.
+#[must_use = "sinks do nothing unless polled"]
+pub trait Sink<Item, Waker> {
+    /// The type of value produced by the sink when an error occurs.
+    type Error;
+
+    /// Attempts to prepare the `Sink` to receive a value.
+    ///
+    /// This method must be called and return `Poll::Ready(Ok(()))` prior to
+    /// each call to `start_send`.
+    ///
+    /// This method returns `Poll::Ready` once the underlying sink is ready to
+    /// receive data. If this method returns `Poll::Pending`, the current task
+    /// is registered to be notified (via `waker`) when `poll_ready` should
+    /// be called again.
+    ///
+    /// In most cases, if the sink encounters an error, the sink will
+    /// permanently be unable to receive items.
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>>;
+
+    /// Begin the process of sending a value to the sink.
+    ///
+    /// Each call to this function must be preceded by a successful call to
+    /// `poll_ready` which returned `Poll::Ready(Ok(()))`.
+    ///
+    /// As the name suggests, this method only *begins* the process of
+    /// sending the item. If the sink employs buffering, the item isn't fully
+    /// processed until the buffer has been fully flushed. Since sinks are
+    /// designed to work with asynchronous I/O, the process of actually
+    /// writing out the data to an underlying object takes place
+    /// asynchronously. **You *must* use `poll_flush` or `poll_close` in
+    /// order to guarantee completion of a send**.
+    ///
+    /// Implementations of `poll_ready` and `start_send` will usually involve
+    /// flushing behind the scenes in order to make room for new messages.
+    /// It is only necessary to call `poll_flush` if you need to guarantee
+    /// that *all* of the items placed into the `Sink` have been sent.
+    ///
+    /// In most cases, if the sink encounters an error, the sink will
+    /// permanently be unable to receive items.
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error>;
+
+    /// Flush any remaining output from this sink.
+    ///
+    /// Returns `Poll::Ready(Ok(()))` when no buffered items remain. If this
+    /// value is returned then it is guaranteed that all previous values sent
+    /// via `start_send` have been flushed.
+    ///
+    /// Returns `Poll::Pending` if there is more work left to do, in which
+    /// case the current task is scheduled (via `waker`) to wake up when
+    /// `poll_flush` should be called again.
+    ///
+    /// In most cases, if the sink encounters an error, the sink will
+    /// permanently be unable to receive items.
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>>;
+
+    /// Flush any remaining output and close this sink, if necessary.
+    ///
+    /// Returns `Poll::Ready(Ok(()))` when no buffered items remain and the
+    /// sink has been successfully closed.
+    ///
+    /// Returns `Poll::Pending` if there is more work left to do, in which
+    /// case the current task is scheduled (via `waker`) to wake up when
+    /// `poll_close` should be called again.
+    ///
+    /// If this function encounters an error, the sink should be considered
+    /// to have failed permanently, and no more `Sink` methods should be
+    /// called.
+    fn poll_close(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>>;
+}
+
+impl<Item, Waker, S: ?Sized + Sink<Item, Waker> + Unpin> Sink<Item, Waker>
+    for &mut S
+{
+    type Error = S::Error;
+
+    fn poll_ready(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        S::poll_ready(Pin::new(&mut **self), waker)
+    }
+
+    fn start_send(
+        mut self: Pin<&mut Self>,
+        item: Item,
+    ) -> Result<(), Self::Error> {
+        S::start_send(Pin::new(&mut **self), item)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        S::poll_flush(Pin::new(&mut **self), waker)
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        S::poll_close(Pin::new(&mut **self), waker)
+    }
+}
+
+impl<Item, Waker, P> Sink<Item, Waker> for Pin<P>
+where
+    P: ops::DerefMut<Target: Sink<Item, Waker>>,
+{
+    type Error = <P::Target as Sink<Item, Waker>>::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        <P::Target as Sink<Item, Waker>>::poll_ready(self.as_deref_mut(), waker)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        <P::Target as Sink<Item, Waker>>::start_send(self.as_deref_mut(), item)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        <P::Target as Sink<Item, Waker>>::poll_flush(self.as_deref_mut(), waker)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        <P::Target as Sink<Item, Waker>>::poll_close(self.as_deref_mut(), waker)
+    }
+}
+
 /// temporary trait until Fn::call is stabilized
 pub trait Wake {
     fn wake(&self);
 }
+
+/// Pointer to the [`Wake`] impl of a task, stored inside of the guards used as
+/// wakers.
+///
+/// Guards dereference the pointer they hold whenever they are woken, so a
+/// `WakePtr` that points to a task can only be created through the unsafe
+/// [`WakePtr::new`], whose caller guarantees the task outlives the guards
+/// holding it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WakePtr(Option<NonNull<dyn Wake>>);
+
+impl WakePtr {
+    /// `WakePtr` that doesn't point to any task, so waking it does nothing.
+    pub const NONE: Self = Self(None);
+
+    /// Creates a new `WakePtr` pointing to `wake`.
+    ///
+    /// # Safety
+    ///
+    /// `wake` must remain valid until every guard the returned pointer, or a
+    /// copy of it, is stored in has been dropped or pointed to another task.
+    #[inline]
+    pub const unsafe fn new(wake: NonNull<dyn Wake>) -> Self {
+        Self(Some(wake))
+    }
+
+    /// Returns the pointer to the task `self` points to, if any.
+    #[inline]
+    pub fn as_ptr(self) -> Option<NonNull<dyn Wake>> {
+        self.0
+    }
+
+    /// Wakes the task `self` points to, if any.
+    ///
+    /// # Safety
+    ///
+    /// The task `self` points to must still be valid, which is the case while
+    /// `self` is stored in a guard.
+    #[inline]
+    pub unsafe fn wake(self) {
+        if let Some(wake) = self.0 {
+            unsafe { wake.as_ref().wake() }
+        }
+    }
+}
+
+/// [`WakePtr`] to a task whose [`Wake`] impl is `Sync`, so it can be sent to
+/// and woken from other threads.
+///
+/// This is the pointer held by thread safe guards, which would otherwise not
+/// be `Send` or `Sync`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SyncWakePtr(Option<NonNull<dyn Wake + Sync>>);
+
+// SAFETY: the only access to the pointee is through `&self`, and it is `Sync`.
+unsafe impl Send for SyncWakePtr {}
+
+// SAFETY: see above.
+unsafe impl Sync for SyncWakePtr {}
+
+impl SyncWakePtr {
+    /// `SyncWakePtr` that doesn't point to any task, so waking it does
+    /// nothing.
+    pub const NONE: Self = Self(None);
+
+    /// Creates a new `SyncWakePtr` pointing to `wake`.
+    ///
+    /// # Safety
+    ///
+    /// See [`WakePtr::new`].
+    #[inline]
+    pub const unsafe fn new(wake: NonNull<dyn Wake + Sync>) -> Self {
+        Self(Some(wake))
+    }
+
+    /// Returns the pointer to the task `self` points to, if any.
+    #[inline]
+    pub fn as_ptr(self) -> Option<NonNull<dyn Wake + Sync>> {
+        self.0
+    }
+
+    /// Wakes the task `self` points to, if any.
+    ///
+    /// # Safety
+    ///
+    /// See [`WakePtr::wake`].
+    #[inline]
+    pub unsafe fn wake(self) {
+        if let Some(wake) = self.0 {
+            unsafe { wake.as_ref().wake() }
+        }
+    }
+}
+
+impl From<SyncWakePtr> for WakePtr {
+    #[inline]
+    fn from(wake: SyncWakePtr) -> Self {
+        Self(wake.0.map(|wake| wake as NonNull<dyn Wake>))
+    }
+}
+
+/// Guard used as a waker, holding the pointer to the task it wakes.
+///
+/// Combinators that poll several futures within one task, like `Join` and
+/// `Race`, hand each future its own child guard, and register a weak
+/// [`Parent`](WakeGuard::Parent) guard to the waker they were polled with, so
+/// waking a child also wakes the task. This trait describes those operations,
+/// so the same combinators work with both single threaded and thread safe
+/// guards.
+///
+/// Implementors are `'static` because they point to tasks through a
+/// [`WakePtr`] or [`SyncWakePtr`]. Those can only be created through their
+/// unsafe constructors, whose callers guarantee the task outlives the guards
+/// pointing to it, which executors uphold by owning the guards of the tasks
+/// they poll. That guarantee is what makes waking a guard safe. Reactors
+/// should bind to a guard through a [`WakerRegistration`] rather than use it
+/// directly.
+pub trait WakeGuard: 'static {
+    /// Weak guard that combinators register to the waker of their parent.
+    type Parent: Default;
+
+    /// Pointer to a task held by `Self`.
+    ///
+    /// Every guard can hold a [`SyncWakePtr`], so code that is generic over
+    /// the guard, like executors, can create one from a `Sync` task.
+    type Ptr: Copy + Default + From<SyncWakePtr>;
+
+    /// Creates a new guard pointing to `wake`.
+    fn new(wake: Self::Ptr) -> Self;
+
+    /// Points `self` to `wake`.
+    fn set(&self, wake: Self::Ptr);
+
+    /// Wakes the task `self` points to, if any.
+    fn wake(&self);
+
+    /// Binds `parent` to `self`, unbinding any guard it was previously
+    /// registered to.
+    fn register_parent<'a>(self: Pin<&'a Self>, parent: Pin<&'a Self::Parent>);
+
+    /// Wakes the task of the guard `parent` is registered to, if any.
+    fn wake_parent(parent: &Self::Parent);
+}
@@ -0,0 +1,95 @@
+//! Binding reactors to the task being polled.
+//!
+//! A reactor that needs to wake a task later owns a pinned [`WakerSlot`],
+//! which it binds to the task through the [`WakerRegistration`] made from the
+//! waker it was polled with. The binding is undone when either the waker or
+//! the slot is dropped, so the slot can never wake a task that no longer
+//! exists, and neither the reactor nor the futures polling it need to touch a
+//! [`WakePtr`](crate::WakePtr).
+
+use core::{marker::PhantomPinned, pin::Pin};
+
+use crate::WakeGuard;
+
+/// Short-lived handle to the waker of the task being polled, used to bind a
+/// [`WakerSlot`] to that task.
+///
+/// This only lives for the duration of a `poll`, so the waker itself can
+/// never be stored by the reactor.
+pub struct WakerRegistration<'poll, Waker> {
+    waker: Pin<&'poll Waker>,
+}
+
+impl<'poll, Waker: WakeGuard> WakerRegistration<'poll, Waker> {
+    /// Creates a new `WakerRegistration` for `waker`.
+    #[inline]
+    pub fn new(waker: Pin<&'poll Waker>) -> Self {
+        Self { waker }
+    }
+
+    /// Binds `slot` to the task being polled, so [`WakerSlot::wake`] wakes
+    /// it.
+    ///
+    /// A waker can only be bound to one slot at a time, so this unbinds any
+    /// slot that was previously bound to the same waker, as well as any task
+    /// `slot` was previously bound to.
+    #[inline]
+    pub fn register(self, slot: Pin<&WakerSlot<Waker>>) {
+        self.waker.register_parent(unsafe {
+            slot.map_unchecked(|slot| &slot.parent)
+        });
+    }
+}
+
+/// Pinned slot owned by a reactor, which can be bound to a task through a
+/// [`WakerRegistration`] and then used to wake it.
+///
+/// A slot is `Send` and `Sync` whenever the [`Parent`](WakeGuard::Parent) of
+/// its waker is, as is the case for thread safe guards, so it can be woken
+/// from another thread.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct WakerSlot<Waker: WakeGuard> {
+    parent: Waker::Parent,
+    _marker: PhantomPinned,
+}
+
+impl<Waker: WakeGuard> WakerSlot<Waker> {
+    /// Creates a new `WakerSlot` that isn't bound to any task.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            parent: Waker::Parent::default(),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Wakes the task `self` is bound to, if any.
+    ///
+    /// This is safe because `self` can only be bound to a waker that is
+    /// alive, and a waker can only point to a task through the unsafe
+    /// [`WakePtr::new`](crate::WakePtr::new) or
+    /// [`SyncWakePtr::new`](crate::SyncWakePtr::new), whose caller
+    /// guarantees the task outlives it.
+    #[inline]
+    pub fn wake(&self) {
+        Waker::wake_parent(&self.parent);
+    }
+}
+
+impl<Waker: WakeGuard> Default for WakerSlot<Waker> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,75 @@
+//! Fixtures shared by the tests of the crates in this workspace.
+//!
+//! This is not part of the public api, and is only compiled with the
+//! `test-util` feature, which those crates enable as a dev-dependency.
+
+use core::{
+    pin::Pin,
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+};
+
+use crate::{SyncWakePtr, Wake, WakeGuard};
+
+/// [`Wake`] that counts how many times it has been woken.
+///
+/// It is `Sync`, so it can be woken through thread safe guards too.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicUsize);
+
+impl Counter {
+    /// Creates a new `Counter` that hasn't been woken.
+    #[inline]
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+
+    /// Returns how many times `self` has been woken.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Wake for Counter {
+    fn wake(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Creates a waker that wakes `counter`.
+///
+/// # Safety
+///
+/// The waker must be dropped before `counter`.
+pub unsafe fn waker<Waker: WakeGuard>(counter: &Counter) -> Waker {
+    let wake: NonNull<dyn Wake + Sync> = NonNull::from(counter);
+    Waker::new(unsafe { SyncWakePtr::new(wake) }.into())
+}
+
+/// Readiness of a mock io object or sink that is only ready on every other
+/// poll, so tests also cover the futures driving it returning
+/// `Poll::Pending`.
+#[derive(Debug, Default)]
+pub struct Alternating(bool);
+
+impl Alternating {
+    /// Creates a new `Alternating`, which is ready on its first poll.
+    #[inline]
+    pub const fn new() -> Self {
+        Self(false)
+    }
+
+    /// Returns `Poll::Ready` on every other call, starting with the first,
+    /// and wakes `waker` whenever it returns `Poll::Pending`.
+    pub fn poll<Waker: WakeGuard>(&mut self, waker: Pin<&Waker>) -> Poll<()> {
+        self.0 = !self.0;
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            waker.wake();
+            Poll::Pending
+        }
+    }
+}
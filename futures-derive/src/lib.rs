@@ -68,25 +68,25 @@ pub fn async_function(_: TokenStream, item: TokenStream) -> TokenStream {
     item_fn.to_token_stream().into()
 }
 
-/// This currently is impossible to do the `futures_compat` workarounds not
-/// being compatible with closures.
-///
-/// Takes async fn that returns anonymous `Future` impl.
-/// Generates fn that returns `UnscopedFutureWrapper` wrapper for the the anonymous `Future` impl.
-///
-/// ```rust,ignore
-/// fn [original name]<'a, 'b>(a: &'a A, b: &'b B) -> impl ScopedFuture<'a + 'b, Output = T> + 'a + 'b {
-///   async fn [__inner]<'a, 'b>(a: &'a A, b: &'b B) -> T { [body] } // compilers turns this into -> impl Future<Output = T> + 'a + 'b
-///   unsafe { UnscopedFutureWrapper::from_future(__inner()) }
-/// }
-/// ```
-///
-/// see https://rust-lang.github.io/rfcs/2394-async_await.html#lifetime-capture-in-the-anonymous-future
-/// for more context on lifetime capture
-/// - resulting ScopedFuture needs to be constrained to not outlive the lifetimes of any references
-///
-/// to actually implement this (capture all lifetimes) we use `ScopedFuture<'_> + '_` so the compiler can infer
-/// lifetimes from the anonymous future impl returned by the actual inner async fn
+// This currently is impossible to do the `futures_compat` workarounds not
+// being compatible with closures.
+//
+// Takes async fn that returns anonymous `Future` impl.
+// Generates fn that returns `UnscopedFutureWrapper` wrapper for the the anonymous `Future` impl.
+//
+// ```rust,ignore
+// fn [original name]<'a, 'b>(a: &'a A, b: &'b B) -> impl ScopedFuture<'a + 'b, Output = T> + 'a + 'b {
+//   async fn [__inner]<'a, 'b>(a: &'a A, b: &'b B) -> T { [body] } // compilers turns this into -> impl Future<Output = T> + 'a + 'b
+//   unsafe { UnscopedFutureWrapper::from_future(__inner()) }
+// }
+// ```
+//
+// see https://rust-lang.github.io/rfcs/2394-async_await.html#lifetime-capture-in-the-anonymous-future
+// for more context on lifetime capture
+// - resulting ScopedFuture needs to be constrained to not outlive the lifetimes of any references
+//
+// to actually implement this (capture all lifetimes) we use `ScopedFuture<'_> + '_` so the compiler can infer
+// lifetimes from the anonymous future impl returned by the actual inner async fn
 // #[proc_macro]
 // pub fn closure(input: TokenStream) -> TokenStream {
 //     // let ExprClosure {
@@ -150,8 +150,8 @@ pub fn async_block(input: TokenStream) -> TokenStream {
     .into()
 }
 
-/// Determines if typed pattern contains a reference or dependency on a
-/// lifetime (used for deciding between '_ and 'static ScopedFuture).
+// Determines if typed pattern contains a reference or dependency on a
+// lifetime (used for deciding between '_ and 'static ScopedFuture).
 // fn has_lifetime_dependency(ty: &syn::Type) -> bool {
 //     match ty {
 //         syn::Type::Reference(_) => true,
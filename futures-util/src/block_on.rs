@@ -1,17 +1,197 @@
-use std::{
+//! Running a future to completion on the current thread.
+//!
+//! [`block_on_with`] only polls the future again once it has been woken, and
+//! sleeps in between through a [`Park`] hook. With the `std` feature,
+//! [`block_on`] parks the current thread.
+
+use core::{
     pin::{self, Pin},
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
     task::Poll,
 };
 
-use crate::{LocalWaker, dummy_guard};
+use futures_core::{Future, SyncWakePtr, Wake, WakeGuard};
 
-pub fn block_on<F: futures_core::Future<LocalWaker>>(
-    mut f: Pin<&mut F>,
-) -> F::Output {
-    let dummy_guard = pin::pin!(dummy_guard());
+/// Hook used by [`block_on_with`] to sleep until the future it runs is
+/// woken.
+///
+/// On bare metal this is typically implemented with `WFE`/`SEV` or `WFI`,
+/// and with the `std` feature [`ThreadPark`] parks the current thread.
+pub trait Park {
+    /// Blocks until [`unpark`](Park::unpark) is called.
+    ///
+    /// Returning early is allowed, but an `unpark` that happened since the
+    /// last call to `park` must not be lost.
+    fn park(&self);
+
+    /// Wakes up the current or next call to [`park`](Park::park).
+    ///
+    /// This may be called from other threads or from interrupt handlers.
+    fn unpark(&self);
+}
+
+/// [`Park`] impl that spins, for targets without any way to sleep.
+pub struct Spin;
+
+impl Park for Spin {
+    #[inline]
+    fn park(&self) {
+        core::hint::spin_loop();
+    }
+
+    #[inline]
+    fn unpark(&self) {}
+}
+
+/// [`Park`] impl that parks the thread it was created on.
+#[cfg(feature = "std")]
+pub struct ThreadPark(std::thread::Thread);
+
+#[cfg(feature = "std")]
+impl ThreadPark {
+    /// Creates a new `ThreadPark` for the current thread, which must also be
+    /// the thread calling [`park`](Park::park).
+    #[inline]
+    pub fn current() -> Self {
+        Self(std::thread::current())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Park for ThreadPark {
+    #[inline]
+    fn park(&self) {
+        std::thread::park();
+    }
+
+    #[inline]
+    fn unpark(&self) {
+        self.0.unpark();
+    }
+}
+
+/// [`Wake`] impl of the future run by [`block_on_with`].
+struct Unpark<P> {
+    woken: AtomicBool,
+    park: P,
+}
+
+impl<P: Park> Wake for Unpark<P> {
+    fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        self.park.unpark();
+    }
+}
+
+/// Runs `future` to completion, sleeping through `park` whenever it is
+/// pending.
+///
+/// The future is polled once, and after that only when it has been woken.
+/// `Waker` can be any [`WakeGuard`], so this works for both `LocalWaker`
+/// and `AtomicWaker` futures.
+pub fn block_on_with<Waker, P, F>(park: P, mut future: Pin<&mut F>) -> F::Output
+where
+    Waker: WakeGuard,
+    P: Park + Sync + 'static,
+    F: Future<Waker>,
+{
+    let unpark = Unpark {
+        woken: AtomicBool::new(false),
+        park,
+    };
+    let wake: NonNull<dyn Wake + Sync> = NonNull::from(&unpark);
+    // `waker` is declared after `unpark`, so it is dropped first
+    let waker = pin::pin!(Waker::new(unsafe { SyncWakePtr::new(wake) }.into()));
     loop {
-        if let Poll::Ready(out) = f.as_mut().poll(dummy_guard.as_ref()) {
+        if let Poll::Ready(out) = future.as_mut().poll(waker.as_ref()) {
             return out;
         }
+        while !unpark.woken.swap(false, Ordering::Acquire) {
+            unpark.park.park();
+        }
+    }
+}
+
+/// Runs `future` to completion, parking the current thread whenever it is
+/// pending.
+///
+/// See [`block_on_with`].
+#[cfg(feature = "std")]
+#[inline]
+pub fn block_on<Waker: WakeGuard, F: Future<Waker>>(
+    future: Pin<&mut F>,
+) -> F::Output {
+    block_on_with(ThreadPark::current(), future)
+}
+
+#[cfg(test)]
+mod test {
+    use core::{cell::Cell, pin::pin};
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::{LocalWaker, poll_fn};
+
+    #[test]
+    fn polls_after_wake() {
+        let polls = Cell::new(0);
+        let future = pin!(poll_fn(|waker: Pin<&LocalWaker>| {
+            polls.set(polls.get() + 1);
+            if polls.get() == 3 {
+                return Poll::Ready(polls.get());
+            }
+            waker.wake();
+            Poll::Pending
+        }));
+        assert_eq!(block_on(future), 3);
+    }
+
+    #[cfg(feature = "atomics")]
+    #[test]
+    fn woken_from_thread() {
+        use futures_core::{WakerRegistration, WakerSlot};
+
+        use crate::AtomicWaker;
+
+        let slot = pin!(WakerSlot::<AtomicWaker>::new());
+        let slot = slot.into_ref();
+        let done = AtomicBool::new(false);
+        let polls = Cell::new(0);
+
+        thread::scope(|scope| {
+            let future = pin!(poll_fn(|waker: Pin<&AtomicWaker>| {
+                polls.set(polls.get() + 1);
+                if done.load(Ordering::Acquire) {
+                    return Poll::Ready(());
+                }
+                WakerRegistration::new(waker).register(slot);
+                if polls.get() == 1 {
+                    let done = &done;
+                    scope.spawn(move || {
+                        thread::sleep(Duration::from_millis(10));
+                        done.store(true, Ordering::Release);
+                        slot.wake();
+                    });
+                }
+                Poll::Pending
+            }));
+            block_on(future);
+        });
+        assert_eq!(polls.get(), 2);
+    }
+
+    #[test]
+    fn spin() {
+        let mut woken = false;
+        let future = pin!(poll_fn(|waker: Pin<&LocalWaker>| {
+            if woken {
+                return Poll::Ready(());
+            }
+            woken = true;
+            waker.wake();
+            Poll::Pending
+        }));
+        block_on_with(Spin, future);
     }
 }
@@ -0,0 +1,334 @@
+//! Single-threaded executor for a fixed set of tasks pinned on the caller's
+//! stack.
+//!
+//! Every [`Task`] owns the waker it is polled with, which pushes the task
+//! onto an intrusive ready queue living on the stack of [`run_with`] when it
+//! is woken. Only tasks in the ready queue are polled, and nothing is
+//! allocated, so tasks are free to borrow data from the caller.
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomPinned,
+    pin::{Pin, pin},
+    ptr::NonNull,
+};
+
+use futures_core::{Future, Wake, WakePtr};
+
+use crate::{
+    LocalWaker,
+    block_on::Park,
+    maybe_done::{MaybeDone, maybe_done},
+};
+
+/// Node of the ready queue, shared by every [`Task`] regardless of the type
+/// of its future.
+pub struct TaskHeader {
+    /// Polls the `Task` containing `self`, returning `true` once it
+    /// completed.
+    poll: unsafe fn(NonNull<TaskHeader>) -> bool,
+    /// Ready queue of the executor running `self`, if any.
+    queue: Cell<Option<NonNull<ReadyQueue>>>,
+    next: Cell<Option<NonNull<TaskHeader>>>,
+    /// Set while `self` is in the ready queue, or if it was woken while not
+    /// being run.
+    queued: Cell<bool>,
+    done: Cell<bool>,
+}
+
+impl Wake for TaskHeader {
+    fn wake(&self) {
+        if self.queued.replace(true) {
+            return;
+        }
+        if let Some(queue) = self.queue.get() {
+            unsafe { queue.as_ref() }.push(self);
+        }
+    }
+}
+
+mod private_any_task {
+    pub trait Sealed {}
+}
+
+/// Type erased [`Task`], so tasks with different futures can be run
+/// together.
+///
+/// This is sealed, since the executor polls the `Task` a header belongs to
+/// through it.
+pub trait AnyTask: private_any_task::Sealed {
+    /// Returns the ready queue node of `self`.
+    fn header(&self) -> &TaskHeader;
+}
+
+/// Top-level task run by [`run_with`], holding a future and, once it
+/// completed, its output.
+// `header` must be the first field, so a pointer to it is also a pointer to
+// the `Task`
+#[repr(C)]
+pub struct Task<F: Future<LocalWaker>> {
+    header: TaskHeader,
+    waker: LocalWaker,
+    /* #[pin] */
+    future: UnsafeCell<MaybeDone<F>>,
+    _marker: PhantomPinned,
+}
+
+impl<F: Future<LocalWaker>> Task<F> {
+    /// Creates a new `Task` running `future`.
+    pub fn new(future: F) -> Self {
+        Self {
+            header: TaskHeader {
+                poll: Self::poll,
+                queue: Cell::new(None),
+                next: Cell::new(None),
+                queued: Cell::new(false),
+                done: Cell::new(false),
+            },
+            waker: LocalWaker::new(WakePtr::NONE),
+            future: UnsafeCell::new(maybe_done(future)),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Takes the output of the future, if it completed.
+    #[inline]
+    pub fn take_output(self: Pin<&mut Self>) -> Option<F::Output> {
+        unsafe { Pin::new_unchecked(self.get_unchecked_mut().future.get_mut()) }
+            .take_output()
+    }
+
+    /// # Safety
+    ///
+    /// `header` must point to the header of a pinned `Self`, which is not
+    /// being polled.
+    unsafe fn poll(header: NonNull<TaskHeader>) -> bool {
+        let task = unsafe { header.cast::<Self>().as_ref() };
+        // the waker is dropped along with the header it points to
+        task.waker
+            .set(unsafe { WakePtr::new(NonNull::from(&task.header)) });
+        let future = unsafe { Pin::new_unchecked(&mut *task.future.get()) };
+        future
+            .poll(unsafe { Pin::new_unchecked(&task.waker) })
+            .is_ready()
+    }
+}
+
+impl<F: Future<LocalWaker>> private_any_task::Sealed for Task<F> {}
+
+impl<F: Future<LocalWaker>> AnyTask for Task<F> {
+    #[inline]
+    fn header(&self) -> &TaskHeader {
+        &self.header
+    }
+}
+
+/// Intrusive FIFO of woken tasks, owned by [`run_with`].
+struct ReadyQueue {
+    head: Cell<Option<NonNull<TaskHeader>>>,
+    tail: Cell<Option<NonNull<TaskHeader>>>,
+    park: NonNull<dyn Park>,
+}
+
+impl ReadyQueue {
+    fn push(&self, header: &TaskHeader) {
+        let ptr = NonNull::from(header);
+        header.next.set(None);
+        match self.tail.replace(Some(ptr)) {
+            Some(tail) => unsafe { tail.as_ref() }.next.set(Some(ptr)),
+            None => self.head.set(Some(ptr)),
+        }
+        unsafe { self.park.as_ref() }.unpark();
+    }
+
+    fn pop(&self) -> Option<&TaskHeader> {
+        let head = unsafe { self.head.get()?.as_ref() };
+        self.head.set(head.next.take());
+        if self.head.get().is_none() {
+            self.tail.set(None);
+        }
+        head.queued.set(false);
+        Some(head)
+    }
+}
+
+/// Detaches the tasks attached to `queue` once [`run_with`] returns, even
+/// if it unwinds.
+struct Detach<'a, 't> {
+    tasks: &'a [Pin<&'t dyn AnyTask>],
+    queue: NonNull<ReadyQueue>,
+}
+
+impl Drop for Detach<'_, '_> {
+    fn drop(&mut self) {
+        for task in self.tasks {
+            let header = task.header();
+            // tasks run by an outer executor stay attached to it
+            if header.queue.get() == Some(self.queue) {
+                header.queue.set(None);
+                header.next.set(None);
+            }
+        }
+    }
+}
+
+/// Runs `tasks` until all of them completed, sleeping through `park`
+/// whenever none of them are ready.
+///
+/// Every task is polled once, and after that only when it has been woken.
+/// Tasks that completed in an earlier call are skipped, and their outputs
+/// can be taken with [`Task::take_output`] once this returns. So are tasks
+/// already being run by an executor this is nested in, and a task passed
+/// more than once is only run once.
+///
+/// Wakes must happen on the thread running the executor, such as from
+/// inside of another task.
+pub fn run_with<P>(park: P, tasks: &[Pin<&dyn AnyTask>])
+where
+    P: Park + 'static,
+{
+    let queue = pin!(ReadyQueue {
+        head: Cell::new(None),
+        tail: Cell::new(None),
+        park: NonNull::from(&park as &dyn Park),
+    });
+    let queue = queue.into_ref();
+    let _detach = Detach {
+        tasks,
+        queue: NonNull::from(queue.get_ref()),
+    };
+
+    let mut pending = 0;
+    for task in tasks {
+        let header = task.header();
+        if header.done.get() || header.queue.get().is_some() {
+            continue;
+        }
+        pending += 1;
+        header.queue.set(Some(NonNull::from(queue.get_ref())));
+        header.queued.set(true);
+        queue.push(header);
+    }
+
+    while pending > 0 {
+        let Some(header) = queue.pop() else {
+            park.park();
+            continue;
+        };
+        if header.done.get() {
+            continue;
+        }
+        if unsafe { (header.poll)(NonNull::from(header)) } {
+            header.done.set(true);
+            pending -= 1;
+        }
+    }
+}
+
+/// Runs `tasks` until all of them completed, parking the current thread
+/// whenever none of them are ready.
+///
+/// See [`run_with`].
+#[cfg(feature = "std")]
+#[inline]
+pub fn run(tasks: &[Pin<&dyn AnyTask>]) {
+    run_with(crate::block_on::ThreadPark::current(), tasks);
+}
+
+#[cfg(test)]
+mod test {
+    use core::task::Poll;
+
+    use futures_core::WakeGuard;
+
+    use super::*;
+    use crate::{
+        poll_fn,
+        wait_queue::{WaitQueue, Waiter},
+    };
+
+    #[test]
+    fn only_woken_tasks_are_polled() {
+        let queue = pin!(WaitQueue::new());
+        let queue = queue.into_ref();
+        let waiter = pin!(Waiter::new());
+        let waiter = waiter.into_ref();
+        let polls = [Cell::new(0), Cell::new(0)];
+
+        let mut waiting =
+            pin!(Task::new(poll_fn(|waker: Pin<&LocalWaker>| {
+                polls[0].set(polls[0].get() + 1);
+                waiter.poll_wait(queue, waker)
+            })));
+        let mut waking = pin!(Task::new(poll_fn(|_: Pin<&LocalWaker>| {
+            polls[1].set(polls[1].get() + 1);
+            Poll::Ready(queue.wake_one())
+        })));
+        run(&[waiting.as_ref(), waking.as_ref()]);
+
+        assert_eq!(polls.each_ref().map(Cell::get), [2, 1]);
+        assert_eq!(waiting.as_mut().take_output(), Some(()));
+        assert_eq!(waking.as_mut().take_output(), Some(true));
+
+        // completed tasks aren't polled again
+        run(&[waiting.as_ref(), waking.as_ref()]);
+        assert_eq!(polls.each_ref().map(Cell::get), [2, 1]);
+    }
+
+    #[test]
+    fn borrows_local_data() {
+        let mut items = [0; 4];
+        let len = Cell::new(0);
+        let queue = pin!(WaitQueue::new());
+        let queue = queue.into_ref();
+
+        {
+            let producer =
+                pin!(Task::new(poll_fn(|waker: Pin<&LocalWaker>| {
+                    if len.get() == items.len() {
+                        return Poll::Ready(());
+                    }
+                    items[len.get()] = len.get() * 2;
+                    len.set(len.get() + 1);
+                    queue.wake_all();
+                    waker.wake();
+                    Poll::Pending
+                })));
+            let waiter = pin!(Waiter::new());
+            let waiter = waiter.into_ref();
+            let mut seen = 0;
+            let consumer =
+                pin!(Task::new(poll_fn(|waker: Pin<&LocalWaker>| {
+                    while waiter.poll_wait(queue, waker).is_ready() {
+                        seen = len.get();
+                    }
+                    if seen == 4 {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    }
+                })));
+            run(&[consumer.as_ref(), producer.as_ref()]);
+        }
+
+        assert_eq!(items, [0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn shared_tasks_are_polled_once() {
+        let polls = Cell::new(0);
+        let shared = pin!(Task::new(poll_fn(|_: Pin<&LocalWaker>| {
+            polls.set(polls.get() + 1);
+            Poll::Ready(())
+        })));
+        let shared = shared.into_ref();
+        // `shared` is still attached to the outer run, so this skips it
+        let nested = pin!(Task::new(poll_fn(|_: Pin<&LocalWaker>| {
+            run(&[shared]);
+            Poll::Ready(())
+        })));
+
+        run(&[nested.as_ref(), shared, shared]);
+        assert_eq!(polls.get(), 1);
+    }
+}
@@ -0,0 +1,454 @@
+//! Extension traits and futures for the byte IO traits of `futures_core`.
+//!
+//! Every future here operates on borrowed buffers, so no allocation is
+//! required.
+
+use core::task::{Poll, ready};
+use core::{mem, pin::Pin};
+
+use futures_core::{AsyncBufRead, AsyncRead, AsyncWrite, Future};
+
+/// Error returned by [`read_exact`](AsyncReadExt::read_exact).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadExactError<E> {
+    /// The reader reached the end of the stream before the buffer was
+    /// filled.
+    UnexpectedEof,
+    /// The reader failed.
+    Io(E),
+}
+
+/// Error returned by [`write_all`](AsyncWriteExt::write_all).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteAllError<E> {
+    /// The writer stopped accepting bytes before the whole buffer was
+    /// written.
+    WriteZero,
+    /// The writer failed.
+    Io(E),
+}
+
+/// Error returned by [`copy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyError<R, W> {
+    /// The reader failed.
+    Read(R),
+    /// The writer failed.
+    Write(W),
+    /// The writer stopped accepting bytes before the reader reached the end
+    /// of the stream.
+    WriteZero,
+}
+
+/// Adapters for [`AsyncRead`]ers, implemented for any waker type.
+pub trait AsyncReadExt<Waker>: AsyncRead<Waker> {
+    /// Reads some bytes into `buf`, returning how many bytes were read.
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Read<'a, Self>
+    where
+        Self: Unpin,
+    {
+        Read { reader: self, buf }
+    }
+
+    /// Reads exactly enough bytes to fill `buf`.
+    ///
+    /// Fails with [`ReadExactError::UnexpectedEof`] if the end of the stream
+    /// is reached first, in which case the contents of `buf` are
+    /// unspecified.
+    fn read_exact<'a>(&'a mut self, buf: &'a mut [u8]) -> ReadExact<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadExact { reader: self, buf }
+    }
+}
+
+impl<Waker, R: ?Sized + AsyncRead<Waker>> AsyncReadExt<Waker> for R {}
+
+/// Adapters for [`AsyncWrite`]rs, implemented for any waker type.
+pub trait AsyncWriteExt<Waker>: AsyncWrite<Waker> {
+    /// Writes all of `buf`.
+    ///
+    /// Fails with [`WriteAllError::WriteZero`] if the writer stops accepting
+    /// bytes first.
+    fn write_all<'a>(&'a mut self, buf: &'a [u8]) -> WriteAll<'a, Self>
+    where
+        Self: Unpin,
+    {
+        WriteAll { writer: self, buf }
+    }
+
+    /// Flushes the writer.
+    fn flush(&mut self) -> Flush<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Flush { writer: self }
+    }
+}
+
+impl<Waker, W: ?Sized + AsyncWrite<Waker>> AsyncWriteExt<Waker> for W {}
+
+/// Adapters for [`AsyncBufRead`]ers, implemented for any waker type.
+pub trait AsyncBufReadExt<Waker>: AsyncBufRead<Waker> {
+    /// Reads bytes into `buf` until `delimiter` is found, the end of the
+    /// stream is reached, or `buf` is full, returning how many bytes were
+    /// read.
+    ///
+    /// If found, `delimiter` is included in the bytes read. Bytes after
+    /// `delimiter` are left in the reader.
+    fn read_until<'a>(
+        &'a mut self,
+        delimiter: u8,
+        buf: &'a mut [u8],
+    ) -> ReadUntil<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadUntil {
+            reader: self,
+            delimiter,
+            buf,
+            read: 0,
+        }
+    }
+}
+
+impl<Waker, R: ?Sized + AsyncBufRead<Waker>> AsyncBufReadExt<Waker> for R {}
+
+/// Copies the entire contents of `reader` into `writer`, returning how many
+/// bytes were copied.
+///
+/// Bytes are written straight out of the internal buffer of `reader`, so no
+/// intermediate buffer is needed. `writer` is flushed once the end of the
+/// stream is reached.
+pub fn copy<'a, R, W>(reader: &'a mut R, writer: &'a mut W) -> Copy<'a, R, W>
+where
+    R: ?Sized + Unpin,
+    W: ?Sized + Unpin,
+{
+    Copy {
+        reader,
+        writer,
+        amt: 0,
+    }
+}
+
+/// Future for the [`read`](AsyncReadExt::read) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Read<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut [u8],
+}
+
+impl<Waker, R> Future<Waker> for Read<'_, R>
+where
+    R: ?Sized + AsyncRead<Waker> + Unpin,
+{
+    type Output = Result<usize, R::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        Pin::new(&mut *this.reader).poll_read(waker, this.buf)
+    }
+}
+
+/// Future for the [`read_exact`](AsyncReadExt::read_exact) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ReadExact<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut [u8],
+}
+
+impl<Waker, R> Future<Waker> for ReadExact<'_, R>
+where
+    R: ?Sized + AsyncRead<Waker> + Unpin,
+{
+    type Output = Result<(), ReadExactError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while !this.buf.is_empty() {
+            let n =
+                ready!(Pin::new(&mut *this.reader).poll_read(waker, this.buf))
+                    .map_err(ReadExactError::Io)?;
+            if n == 0 {
+                return Poll::Ready(Err(ReadExactError::UnexpectedEof));
+            }
+            this.buf = &mut mem::take(&mut this.buf)[n..];
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future for the [`write_all`](AsyncWriteExt::write_all) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WriteAll<'a, W: ?Sized> {
+    writer: &'a mut W,
+    buf: &'a [u8],
+}
+
+impl<Waker, W> Future<Waker> for WriteAll<'_, W>
+where
+    W: ?Sized + AsyncWrite<Waker> + Unpin,
+{
+    type Output = Result<(), WriteAllError<W::Error>>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while !this.buf.is_empty() {
+            let n =
+                ready!(Pin::new(&mut *this.writer).poll_write(waker, this.buf))
+                    .map_err(WriteAllError::Io)?;
+            if n == 0 {
+                return Poll::Ready(Err(WriteAllError::WriteZero));
+            }
+            this.buf = &this.buf[n..];
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future for the [`flush`](AsyncWriteExt::flush) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Flush<'a, W: ?Sized> {
+    writer: &'a mut W,
+}
+
+impl<Waker, W> Future<Waker> for Flush<'_, W>
+where
+    W: ?Sized + AsyncWrite<Waker> + Unpin,
+{
+    type Output = Result<(), W::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        Pin::new(&mut *self.get_mut().writer).poll_flush(waker)
+    }
+}
+
+/// Future for the [`read_until`](AsyncBufReadExt::read_until) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ReadUntil<'a, R: ?Sized> {
+    reader: &'a mut R,
+    delimiter: u8,
+    buf: &'a mut [u8],
+    read: usize,
+}
+
+impl<Waker, R> Future<Waker> for ReadUntil<'_, R>
+where
+    R: ?Sized + AsyncBufRead<Waker> + Unpin,
+{
+    type Output = Result<usize, R::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while this.read < this.buf.len() {
+            let available =
+                ready!(Pin::new(&mut *this.reader).poll_fill_buf(waker))?;
+            if available.is_empty() {
+                break;
+            }
+
+            let remaining = &mut this.buf[this.read..];
+            let (amt, done) = match available
+                .iter()
+                .take(remaining.len())
+                .position(|&byte| byte == this.delimiter)
+            {
+                Some(index) => (index + 1, true),
+                None => (available.len().min(remaining.len()), false),
+            };
+            remaining[..amt].copy_from_slice(&available[..amt]);
+            Pin::new(&mut *this.reader).consume(amt);
+            this.read += amt;
+
+            if done {
+                break;
+            }
+        }
+        Poll::Ready(Ok(mem::take(&mut this.read)))
+    }
+}
+
+/// Future for the [`copy`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Copy<'a, R: ?Sized, W: ?Sized> {
+    reader: &'a mut R,
+    writer: &'a mut W,
+    amt: u64,
+}
+
+impl<Waker, R, W> Future<Waker> for Copy<'_, R, W>
+where
+    R: ?Sized + AsyncBufRead<Waker> + Unpin,
+    W: ?Sized + AsyncWrite<Waker> + Unpin,
+{
+    type Output = Result<u64, CopyError<R::Error, W::Error>>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let buf = ready!(Pin::new(&mut *this.reader).poll_fill_buf(waker))
+                .map_err(CopyError::Read)?;
+            if buf.is_empty() {
+                ready!(Pin::new(&mut *this.writer).poll_flush(waker))
+                    .map_err(CopyError::Write)?;
+                return Poll::Ready(Ok(this.amt));
+            }
+
+            let n = ready!(Pin::new(&mut *this.writer).poll_write(waker, buf))
+                .map_err(CopyError::Write)?;
+            if n == 0 {
+                return Poll::Ready(Err(CopyError::WriteZero));
+            }
+            Pin::new(&mut *this.reader).consume(n);
+            this.amt += n as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{convert::Infallible, pin::pin};
+
+    use futures_core::{WakeGuard, test_util::Alternating};
+
+    use super::*;
+    use crate::{LocalWaker, block_on::block_on};
+
+    /// Writer into a fixed buffer that accepts at most 3 bytes at a time.
+    struct Chunked<const N: usize> {
+        buf: [u8; N],
+        len: usize,
+        readiness: Alternating,
+    }
+
+    impl<const N: usize> Chunked<N> {
+        fn new() -> Self {
+            Self {
+                buf: [0; N],
+                len: 0,
+                readiness: Alternating::new(),
+            }
+        }
+
+        fn written(&self) -> &[u8] {
+            &self.buf[..self.len]
+        }
+    }
+
+    impl<Waker: WakeGuard, const N: usize> AsyncWrite<Waker> for Chunked<N> {
+        type Error = Infallible;
+
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            waker: Pin<&Waker>,
+            buf: &[u8],
+        ) -> Poll<Result<usize, Self::Error>> {
+            ready!(self.readiness.poll(waker));
+            let len = self.len;
+            let amt = buf.len().min(3).min(N - len);
+            self.buf[len..len + amt].copy_from_slice(&buf[..amt]);
+            self.len += amt;
+            Poll::Ready(Ok(amt))
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            _waker: Pin<&Waker>,
+        ) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            self: Pin<&mut Self>,
+            _waker: Pin<&Waker>,
+        ) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn read_exact() {
+        let mut reader = &b"hello world"[..];
+        let mut buf = [0; 5];
+        let future = pin!(AsyncReadExt::<LocalWaker>::read_exact(
+            &mut reader,
+            &mut buf
+        ));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!(&buf, b"hello");
+        assert_eq!(reader, b" world");
+
+        let mut buf = [0; 10];
+        let future = pin!(AsyncReadExt::<LocalWaker>::read_exact(
+            &mut reader,
+            &mut buf
+        ));
+        assert_eq!(
+            block_on::<LocalWaker, _>(future),
+            Err(ReadExactError::UnexpectedEof)
+        );
+    }
+
+    #[test]
+    fn write_all() {
+        let mut writer = Chunked::<8>::new();
+        let future = pin!(AsyncWriteExt::<LocalWaker>::write_all(
+            &mut writer,
+            b"chunked"
+        ));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!(writer.written(), b"chunked");
+
+        let future =
+            pin!(AsyncWriteExt::<LocalWaker>::write_all(&mut writer, b"full"));
+        assert_eq!(
+            block_on::<LocalWaker, _>(future),
+            Err(WriteAllError::WriteZero)
+        );
+    }
+
+    #[test]
+    fn read_until() {
+        let mut reader = &b"one\ntwo\nthree"[..];
+        let mut buf = [0; 8];
+
+        let future = pin!(AsyncBufReadExt::<LocalWaker>::read_until(
+            &mut reader,
+            b'\n',
+            &mut buf
+        ));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(4));
+        assert_eq!(&buf[..4], b"one\n");
+
+        // stops once the buffer is full
+        let future = pin!(AsyncBufReadExt::<LocalWaker>::read_until(
+            &mut reader,
+            b'\n',
+            &mut buf[..2]
+        ));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(2));
+        assert_eq!(&buf[..2], b"tw");
+        assert_eq!(reader, b"o\nthree");
+
+        // stops at the end of the stream
+        let mut reader = &b"three"[..];
+        let future = pin!(AsyncBufReadExt::<LocalWaker>::read_until(
+            &mut reader,
+            b'\n',
+            &mut buf
+        ));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(5));
+    }
+
+    #[test]
+    fn copy() {
+        let mut reader = &b"copied bytes"[..];
+        let mut writer = Chunked::<16>::new();
+        let future = pin!(super::copy(&mut reader, &mut writer));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(12));
+        assert_eq!(writer.written(), b"copied bytes");
+    }
+}
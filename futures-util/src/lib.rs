@@ -1,55 +1,69 @@
-use std::{pin::Pin, ptr::NonNull, task::Poll};
+#![no_std]
+
+#[cfg(feature = "std")]
+extern crate std;
+
+use core::{marker::PhantomData, pin::Pin, ptr::NonNull, task::Poll};
 
 use futures_core::{Future, Wake};
-use lifetime_guard::{atomic_guard::AtomicValueGuard, guard::ValueGuard};
+#[cfg(feature = "atomics")]
+use lifetime_guard::atomic_guard::AtomicValueGuard;
+use lifetime_guard::guard::ValueGuard;
 
 pub mod block_on;
+pub mod executor;
+pub mod io;
 pub mod maybe_done;
+pub mod sink;
+pub mod stream;
+pub mod try_future;
+pub mod wait_queue;
 
-pub type WakePtr = Option<NonNull<dyn Wake>>;
+pub use futures_core::{SyncWakePtr, WakePtr};
 pub type LocalWaker = ValueGuard<WakePtr>;
-pub type AtomicWaker = AtomicValueGuard<WakePtr>;
+#[cfg(feature = "atomics")]
+pub type AtomicWaker = AtomicValueGuard<SyncWakePtr>;
 
-pub(crate) fn assert_future<T, F>(future: F) -> F
+pub(crate) fn assert_future<Waker, T, F>(future: F) -> F
 where
-    F: Future<LocalWaker, Output = T>,
+    F: Future<Waker, Output = T>,
 {
     future
 }
 
-pub struct PollFn<F, T>(F)
-where
-    F: FnMut(&LocalWaker) -> Poll<T>;
+pub struct PollFn<F, T>(F, PhantomData<fn() -> T>);
 
-impl<F, T> futures_core::Future<LocalWaker> for PollFn<F, T>
+impl<Waker, F, T> futures_core::Future<Waker> for PollFn<F, T>
 where
-    F: FnMut(&LocalWaker) -> Poll<T>,
+    F: FnMut(Pin<&Waker>) -> Poll<T>,
 {
     type Output = T;
 
-    fn poll(
-        self: Pin<&mut Self>,
-        waker: Pin<&LocalWaker>,
-    ) -> Poll<Self::Output> {
-        (unsafe { &mut self.get_unchecked_mut().0 })(&waker)
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        (unsafe { &mut self.get_unchecked_mut().0 })(waker)
     }
 }
 
-pub fn poll_fn<F, T>(f: F) -> impl futures_core::Future<LocalWaker, Output = T>
+pub fn poll_fn<Waker, F, T>(
+    f: F,
+) -> impl futures_core::Future<Waker, Output = T>
 where
-    F: FnMut(&LocalWaker) -> Poll<T>,
+    F: FnMut(Pin<&Waker>) -> Poll<T>,
 {
-    PollFn(f)
+    PollFn(f, PhantomData)
 }
 
 pub struct DummyWaker;
 
 impl Wake for DummyWaker {
-    fn wake(&self) {
-        dbg!("awake!");
-    }
+    fn wake(&self) {}
 }
 
 pub fn dummy_guard() -> ValueGuard<WakePtr> {
-    ValueGuard::new(NonNull::new(&mut DummyWaker as *mut dyn Wake))
+    ValueGuard::new(dummy_wake_ptr())
+}
+
+pub(crate) fn dummy_wake_ptr() -> WakePtr {
+    // `&DummyWaker` is promoted to a `'static` reference
+    unsafe { WakePtr::new(NonNull::from(&DummyWaker)) }
 }
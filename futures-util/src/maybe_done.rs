@@ -7,15 +7,15 @@ use crate::LocalWaker;
 use super::assert_future;
 use core::mem;
 use core::pin::Pin;
+use core::task::Poll;
+use core::task::ready;
 use futures_core::Future;
-use std::task::Poll;
-use std::task::ready;
 
 /// A future that may have completed.
 ///
 /// This is created by the [`maybe_done()`] function.
 #[derive(Debug)]
-pub enum MaybeDone<Fut: futures_core::Future<LocalWaker>> {
+pub enum MaybeDone<Fut: futures_core::Future<Waker>, Waker = LocalWaker> {
     /// A not-yet-completed future
     Future(/* #[pin] */ Fut),
     /// The output of the completed future
@@ -25,16 +25,16 @@ pub enum MaybeDone<Fut: futures_core::Future<LocalWaker>> {
     Gone,
 }
 
-impl<Fut: Future<LocalWaker> + Unpin> Unpin for MaybeDone<Fut> {}
+impl<Waker, Fut: Future<Waker> + Unpin> Unpin for MaybeDone<Fut, Waker> {}
 
 /// Wraps a future into a `MaybeDone`
-pub fn maybe_done<Fut: futures_core::Future<LocalWaker>>(
+pub fn maybe_done<Waker, Fut: futures_core::Future<Waker>>(
     future: Fut,
-) -> MaybeDone<Fut> {
-    assert_future::<(), _>(MaybeDone::Future(future))
+) -> MaybeDone<Fut, Waker> {
+    assert_future::<Waker, (), _>(MaybeDone::Future(future))
 }
 
-impl<Fut: Future<LocalWaker>> MaybeDone<Fut> {
+impl<Waker, Fut: Future<Waker>> MaybeDone<Fut, Waker> {
     /// Returns an [`Option`] containing a mutable reference to the output of the future.
     /// The output of this method will be [`Some`] if and only if the inner
     /// future has been completed and [`take_output`](MaybeDone::take_output)
@@ -66,7 +66,7 @@ impl<Fut: Future<LocalWaker>> MaybeDone<Fut> {
     }
 }
 
-impl<Fut: Future<LocalWaker>> FusedFuture<LocalWaker> for MaybeDone<Fut> {
+impl<Waker, Fut: Future<Waker>> FusedFuture<Waker> for MaybeDone<Fut, Waker> {
     fn is_terminated(&self) -> bool {
         match self {
             Self::Future(_) => false,
@@ -75,14 +75,14 @@ impl<Fut: Future<LocalWaker>> FusedFuture<LocalWaker> for MaybeDone<Fut> {
     }
 }
 
-impl<Fut: Future<LocalWaker>> futures_core::Future<LocalWaker>
-    for MaybeDone<Fut>
+impl<Waker, Fut: Future<Waker>> futures_core::Future<Waker>
+    for MaybeDone<Fut, Waker>
 {
     type Output = ();
 
     fn poll(
         mut self: Pin<&mut Self>,
-        waker: Pin<&LocalWaker>,
+        waker: Pin<&Waker>,
     ) -> Poll<Self::Output> {
         unsafe {
             match self.as_mut().get_unchecked_mut() {
@@ -0,0 +1,378 @@
+//! Adapters and futures for [`Sink`]s.
+
+use core::task::{Poll, ready};
+use core::{marker::PhantomData, pin::Pin};
+
+use futures_core::{Future, Sink, TryStream};
+
+/// Adapters for [`Sink`]s, implemented for any waker type.
+pub trait SinkExt<Item, Waker>: Sink<Item, Waker> {
+    /// Composes a function in front of the sink.
+    ///
+    /// Each value sent to the returned sink is first passed to `f`, and the
+    /// future it returns is run to completion before its output is sent to
+    /// `self`.
+    fn with<U, Fut, F, E>(self, f: F) -> With<Self, Item, U, Fut, F>
+    where
+        F: FnMut(U) -> Fut,
+        Fut: Future<Waker, Output = Result<Item, E>>,
+        E: From<Self::Error>,
+        Self: Sized,
+    {
+        With {
+            sink: self,
+            f,
+            state: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sends `item` to the sink, without flushing it.
+    ///
+    /// Unlike [`send`](SinkExt::send), the returned future completes as soon
+    /// as `item` has been handed to the sink, which may still be buffering
+    /// it.
+    fn feed(&mut self, item: Item) -> Feed<'_, Self, Item>
+    where
+        Self: Unpin,
+    {
+        Feed {
+            sink: self,
+            item: Some(item),
+        }
+    }
+
+    /// Sends `item` to the sink, then flushes it.
+    fn send(&mut self, item: Item) -> Send<'_, Self, Item>
+    where
+        Self: Unpin,
+    {
+        Send {
+            feed: self.feed(item),
+        }
+    }
+
+    /// Sends every item of `stream` to the sink, flushing it whenever the
+    /// stream has nothing ready, and once the stream is exhausted.
+    ///
+    /// The first error, from either `stream` or the sink, is returned.
+    fn send_all<'a, St>(
+        &'a mut self,
+        stream: &'a mut St,
+    ) -> SendAll<'a, Self, St, Item>
+    where
+        St: ?Sized + TryStream<Waker, Ok = Item, Error = Self::Error> + Unpin,
+        Self: Unpin,
+    {
+        SendAll {
+            sink: self,
+            stream,
+            buffered: None,
+            stream_done: false,
+        }
+    }
+}
+
+impl<Item, Waker, Si: ?Sized + Sink<Item, Waker>> SinkExt<Item, Waker> for Si {}
+
+/// Sink for the [`with`](SinkExt::with) method.
+#[must_use = "sinks do nothing unless polled"]
+pub struct With<Si, Item, U, Fut, F> {
+    /* #[pin] */
+    sink: Si,
+    f: F,
+    /* #[pin] */
+    state: Option<Fut>,
+    _phantom: PhantomData<fn(U) -> Item>,
+}
+
+impl<Si, Item, U, Fut, F> With<Si, Item, U, Fut, F> {
+    /// Completes the pending future created by `f`, if any, and sends its
+    /// output to the underlying sink.
+    fn poll_state<Waker, E>(
+        self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), E>>
+    where
+        Si: Sink<Item, Waker>,
+        Fut: Future<Waker, Output = Result<Item, E>>,
+        E: From<Si::Error>,
+    {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(future) = this.state.as_mut() {
+            let item =
+                ready!(unsafe { Pin::new_unchecked(future) }.poll(waker))?;
+            this.state = None;
+            unsafe { Pin::new_unchecked(&mut this.sink) }.start_send(item)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn sink(self: Pin<&mut Self>) -> Pin<&mut Si> {
+        unsafe { self.map_unchecked_mut(|this| &mut this.sink) }
+    }
+}
+
+impl<Waker, Si, Item, U, Fut, F, E> Sink<U, Waker> for With<Si, Item, U, Fut, F>
+where
+    Si: Sink<Item, Waker>,
+    F: FnMut(U) -> Fut,
+    Fut: Future<Waker, Output = Result<Item, E>>,
+    E: From<Si::Error>,
+{
+    type Error = E;
+
+    fn poll_ready(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_state(waker))?;
+        Poll::Ready(Ok(ready!(self.sink().poll_ready(waker))?))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: U) -> Result<(), Self::Error> {
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(this.state.is_none(), "With sent to before it was ready");
+        this.state = Some((this.f)(item));
+        Ok(())
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_state(waker))?;
+        Poll::Ready(Ok(ready!(self.sink().poll_flush(waker))?))
+    }
+
+    fn poll_close(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_state(waker))?;
+        Poll::Ready(Ok(ready!(self.sink().poll_close(waker))?))
+    }
+}
+
+/// Future for the [`feed`](SinkExt::feed) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Feed<'a, Si: ?Sized, Item> {
+    sink: &'a mut Si,
+    item: Option<Item>,
+}
+
+// `item` is never pinned
+impl<Si: ?Sized, Item> Unpin for Feed<'_, Si, Item> {}
+
+impl<Waker, Si, Item> Future<Waker> for Feed<'_, Si, Item>
+where
+    Si: ?Sized + Sink<Item, Waker> + Unpin,
+{
+    type Output = Result<(), Si::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut sink = Pin::new(&mut *this.sink);
+        ready!(sink.as_mut().poll_ready(waker))?;
+        let item = this.item.take().expect("Feed polled after completion");
+        Poll::Ready(sink.start_send(item))
+    }
+}
+
+/// Future for the [`send`](SinkExt::send) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Send<'a, Si: ?Sized, Item> {
+    feed: Feed<'a, Si, Item>,
+}
+
+impl<Waker, Si, Item> Future<Waker> for Send<'_, Si, Item>
+where
+    Si: ?Sized + Sink<Item, Waker> + Unpin,
+{
+    type Output = Result<(), Si::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.feed.item.is_some() {
+            ready!(Pin::new(&mut this.feed).poll(waker))?;
+        }
+        Pin::new(&mut *this.feed.sink).poll_flush(waker)
+    }
+}
+
+/// Future for the [`send_all`](SinkExt::send_all) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SendAll<'a, Si: ?Sized, St: ?Sized, Item> {
+    sink: &'a mut Si,
+    stream: &'a mut St,
+    buffered: Option<Item>,
+    stream_done: bool,
+}
+
+// `buffered` is never pinned
+impl<Si: ?Sized, St: ?Sized, Item> Unpin for SendAll<'_, Si, St, Item> {}
+
+impl<Waker, Si, St, Item> Future<Waker> for SendAll<'_, Si, St, Item>
+where
+    Si: ?Sized + Sink<Item, Waker> + Unpin,
+    St: ?Sized + TryStream<Waker, Ok = Item, Error = Si::Error> + Unpin,
+{
+    type Output = Result<(), Si::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let mut sink = Pin::new(&mut *this.sink);
+        loop {
+            if let Some(item) = this.buffered.take() {
+                if sink.as_mut().poll_ready(waker)?.is_pending() {
+                    this.buffered = Some(item);
+                    return Poll::Pending;
+                }
+                sink.as_mut().start_send(item)?;
+            }
+
+            if this.stream_done {
+                return sink.poll_flush(waker);
+            }
+            match Pin::new(&mut *this.stream).try_poll_next(waker)? {
+                Poll::Ready(Some(item)) => this.buffered = Some(item),
+                Poll::Ready(None) => this.stream_done = true,
+                Poll::Pending => {
+                    ready!(sink.poll_flush(waker))?;
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use core::pin::pin;
+
+    use futures_core::{Stream, WakeGuard, test_util::Alternating};
+
+    use super::*;
+    use crate::{LocalWaker, block_on::block_on, poll_fn};
+
+    /// Sink collecting into a fixed buffer, which fails once full.
+    pub(crate) struct Collect<const N: usize> {
+        pub(crate) items: [i32; N],
+        pub(crate) len: usize,
+        pub(crate) flushed: usize,
+        pub(crate) closed: bool,
+        readiness: Alternating,
+    }
+
+    impl<const N: usize> Collect<N> {
+        pub(crate) fn new() -> Self {
+            Self {
+                items: [0; N],
+                len: 0,
+                flushed: 0,
+                closed: false,
+                readiness: Alternating::new(),
+            }
+        }
+    }
+
+    impl<Waker: WakeGuard, const N: usize> Sink<i32, Waker> for Collect<N> {
+        type Error = &'static str;
+
+        fn poll_ready(
+            mut self: Pin<&mut Self>,
+            waker: Pin<&Waker>,
+        ) -> Poll<Result<(), Self::Error>> {
+            ready!(self.readiness.poll(waker));
+            if self.len < N {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Ready(Err("full"))
+            }
+        }
+
+        fn start_send(
+            mut self: Pin<&mut Self>,
+            item: i32,
+        ) -> Result<(), Self::Error> {
+            let len = self.len;
+            self.items[len] = item;
+            self.len += 1;
+            Ok(())
+        }
+
+        fn poll_flush(
+            mut self: Pin<&mut Self>,
+            _waker: Pin<&Waker>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.flushed = self.len;
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(
+            mut self: Pin<&mut Self>,
+            waker: Pin<&Waker>,
+        ) -> Poll<Result<(), Self::Error>> {
+            ready!(self.as_mut().poll_flush(waker))?;
+            self.closed = true;
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Stream yielding the items of an iterator.
+    pub(crate) struct Iter<I>(pub(crate) I);
+
+    impl<I: Iterator + Unpin> Stream<LocalWaker> for Iter<I> {
+        type Item = I::Item;
+
+        fn poll_next(
+            mut self: Pin<&mut Self>,
+            _waker: Pin<&LocalWaker>,
+        ) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.0.next())
+        }
+    }
+
+    #[test]
+    fn feed_and_send() {
+        let mut sink = Collect::<4>::new();
+
+        let future = pin!(SinkExt::<_, LocalWaker>::feed(&mut sink, 1));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!((sink.len, sink.flushed), (1, 0));
+
+        let future = pin!(SinkExt::<_, LocalWaker>::send(&mut sink, 2));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!((sink.len, sink.flushed), (2, 2));
+        assert_eq!(sink.items[..2], [1, 2]);
+    }
+
+    #[test]
+    fn send_all() {
+        let mut sink = Collect::<4>::new();
+        let mut stream = Iter([1, 2, 3].into_iter().map(Ok));
+        let future = pin!(sink.send_all(&mut stream));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!(sink.items[..sink.flushed], [1, 2, 3]);
+
+        // errors from the sink are returned
+        let mut stream = Iter([4, 5].into_iter().map(Ok));
+        let future = pin!(sink.send_all(&mut stream));
+        assert_eq!(block_on::<LocalWaker, _>(future), Err("full"));
+        assert_eq!(sink.items, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn with() {
+        let mut sink = pin!(Collect::<4>::new().with(|x: i32| {
+            let mut x = Some(x);
+            poll_fn(move |_| Poll::Ready(Ok::<_, &str>(x.take().unwrap() * 2)))
+        }));
+
+        let future = pin!(sink.send(1));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        let future = pin!(sink.send(2));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!(sink.sink.items[..2], [2, 4]);
+    }
+}
@@ -0,0 +1,112 @@
+//! Adapters and futures for [`Stream`]s.
+
+use core::pin::Pin;
+use core::task::{Poll, ready};
+
+use futures_core::{Future, Sink, Stream, TryStream};
+
+/// Adapters for [`Stream`]s, implemented for any waker type.
+pub trait StreamExt<Waker>: Stream<Waker> {
+    /// Runs this stream to completion, sending every item to `sink`.
+    ///
+    /// `sink` is flushed whenever the stream has nothing ready, and closed
+    /// once the stream is exhausted. The first error, from either the stream
+    /// or `sink`, is returned.
+    fn forward<Si, Item, E>(self, sink: Si) -> Forward<Self, Si, Item>
+    where
+        Si: Sink<Item, Waker, Error = E>,
+        Self: TryStream<Waker, Ok = Item, Error = E> + Sized,
+    {
+        Forward {
+            stream: self,
+            sink: Some(sink),
+            buffered: None,
+            stream_done: false,
+        }
+    }
+}
+
+impl<Waker, St: ?Sized + Stream<Waker>> StreamExt<Waker> for St {}
+
+/// Future for the [`forward`](StreamExt::forward) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Forward<St, Si, Item> {
+    /* #[pin] */
+    stream: St,
+    /* #[pin] */
+    sink: Option<Si>,
+    buffered: Option<Item>,
+    stream_done: bool,
+}
+
+impl<Waker, St, Si, Item, E> Future<Waker> for Forward<St, Si, Item>
+where
+    Si: Sink<Item, Waker, Error = E>,
+    St: TryStream<Waker, Ok = Item, Error = E>,
+{
+    type Output = Result<(), E>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut sink = unsafe {
+            Pin::new_unchecked(
+                this.sink.as_mut().expect("Forward polled after completion"),
+            )
+        };
+        loop {
+            if let Some(item) = this.buffered.take() {
+                if sink.as_mut().poll_ready(waker)?.is_pending() {
+                    this.buffered = Some(item);
+                    return Poll::Pending;
+                }
+                sink.as_mut().start_send(item)?;
+            }
+
+            if this.stream_done {
+                ready!(sink.poll_close(waker))?;
+                this.sink = None;
+                return Poll::Ready(Ok(()));
+            }
+            match unsafe { Pin::new_unchecked(&mut this.stream) }
+                .try_poll_next(waker)?
+            {
+                Poll::Ready(Some(item)) => this.buffered = Some(item),
+                Poll::Ready(None) => this.stream_done = true,
+                Poll::Pending => {
+                    ready!(sink.poll_flush(waker))?;
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin::pin;
+
+    use super::*;
+    use crate::{
+        LocalWaker,
+        block_on::block_on,
+        sink::test::{Collect, Iter},
+    };
+
+    #[test]
+    fn forward() {
+        let mut sink = Collect::<4>::new();
+        let stream = Iter([1, 2, 3].into_iter().map(Ok));
+        let future = pin!(stream.forward(&mut sink));
+        assert_eq!(block_on::<LocalWaker, _>(future), Ok(()));
+        assert_eq!(sink.items[..sink.flushed], [1, 2, 3]);
+        assert!(sink.closed);
+
+        // errors from the stream are returned
+        let mut sink = Collect::<4>::new();
+        let stream = Iter([Ok(1), Err("stream"), Ok(2)].into_iter());
+        let future = pin!(stream.forward(&mut sink));
+        assert_eq!(block_on::<LocalWaker, _>(future), Err("stream"));
+        assert_eq!(sink.items[..sink.len], [1]);
+        assert!(!sink.closed);
+    }
+}
@@ -0,0 +1,344 @@
+//! Adapters for futures that return `Result` values.
+
+use core::pin::Pin;
+use core::task::{Poll, ready};
+
+use futures_core::{Future, TryFuture};
+
+/// Adapters specific to [`Result`]-returning futures.
+///
+/// This is implemented for every [`TryFuture`], for any waker type.
+pub trait TryFutureExt<Waker>: TryFuture<Waker> {
+    /// Maps this future's success value to a different value.
+    ///
+    /// The error value is passed through unchanged.
+    fn map_ok<T, F>(self, f: F) -> MapOk<Self, F>
+    where
+        F: FnOnce(Self::Ok) -> T,
+        Self: Sized,
+    {
+        MapOk {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    /// Maps this future's error value to a different value.
+    ///
+    /// The success value is passed through unchanged.
+    fn map_err<E, F>(self, f: F) -> MapErr<Self, F>
+    where
+        F: FnOnce(Self::Error) -> E,
+        Self: Sized,
+    {
+        MapErr {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    /// Executes another future after this one resolves successfully, passing
+    /// it the success value.
+    ///
+    /// If this future resolves to an error, `f` is never called and the error
+    /// is returned instead.
+    fn and_then<Fut, F>(self, f: F) -> AndThen<Self, Fut, F>
+    where
+        F: FnOnce(Self::Ok) -> Fut,
+        Fut: TryFuture<Waker, Error = Self::Error>,
+        Self: Sized,
+    {
+        AndThen {
+            chain: TryChain::First(self, Some(f)),
+        }
+    }
+
+    /// Executes another future if this one resolves to an error, passing it
+    /// the error value.
+    ///
+    /// If this future resolves successfully, `f` is never called and the
+    /// success value is returned instead.
+    fn or_else<Fut, F>(self, f: F) -> OrElse<Self, Fut, F>
+    where
+        F: FnOnce(Self::Error) -> Fut,
+        Fut: TryFuture<Waker, Ok = Self::Ok>,
+        Self: Sized,
+    {
+        OrElse {
+            chain: TryChain::First(self, Some(f)),
+        }
+    }
+
+    /// Calls `f` with a reference to this future's error value, if it
+    /// resolves to one, before passing it on.
+    fn inspect_err<F>(self, f: F) -> InspectErr<Self, F>
+    where
+        F: FnOnce(&Self::Error),
+        Self: Sized,
+    {
+        InspectErr {
+            future: self,
+            f: Some(f),
+        }
+    }
+
+    /// Wraps a [`TryFuture`] into a type that implements [`Future`].
+    ///
+    /// This is useful for passing a generic `TryFuture` to functions that
+    /// expect a `Future`.
+    fn into_future(self) -> IntoFuture<Self>
+    where
+        Self: Sized,
+    {
+        IntoFuture { future: self }
+    }
+}
+
+impl<Waker, Fut: ?Sized + TryFuture<Waker>> TryFutureExt<Waker> for Fut {}
+
+/// Future for the [`map_ok`](TryFutureExt::map_ok) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct MapOk<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Waker, Fut, F, T> Future<Waker> for MapOk<Fut, F>
+where
+    Fut: TryFuture<Waker>,
+    F: FnOnce(Fut::Ok) -> T,
+{
+    type Output = Result<T, Fut::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let output = ready!(
+            unsafe { Pin::new_unchecked(&mut this.future) }.try_poll(waker)
+        );
+        let f = this.f.take().expect("MapOk polled after completion");
+        Poll::Ready(output.map(f))
+    }
+}
+
+/// Future for the [`map_err`](TryFutureExt::map_err) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct MapErr<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Waker, Fut, F, E> Future<Waker> for MapErr<Fut, F>
+where
+    Fut: TryFuture<Waker>,
+    F: FnOnce(Fut::Error) -> E,
+{
+    type Output = Result<Fut::Ok, E>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let output = ready!(
+            unsafe { Pin::new_unchecked(&mut this.future) }.try_poll(waker)
+        );
+        let f = this.f.take().expect("MapErr polled after completion");
+        Poll::Ready(output.map_err(f))
+    }
+}
+
+/// Future for the [`inspect_err`](TryFutureExt::inspect_err) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct InspectErr<Fut, F> {
+    future: Fut,
+    f: Option<F>,
+}
+
+impl<Waker, Fut, F> Future<Waker> for InspectErr<Fut, F>
+where
+    Fut: TryFuture<Waker>,
+    F: FnOnce(&Fut::Error),
+{
+    type Output = Result<Fut::Ok, Fut::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let output = ready!(
+            unsafe { Pin::new_unchecked(&mut this.future) }.try_poll(waker)
+        );
+        let f = this.f.take().expect("InspectErr polled after completion");
+        Poll::Ready(output.inspect_err(f))
+    }
+}
+
+/// Future for the [`into_future`](TryFutureExt::into_future) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct IntoFuture<Fut> {
+    future: Fut,
+}
+
+impl<Waker, Fut: TryFuture<Waker>> Future<Waker> for IntoFuture<Fut> {
+    type Output = Result<Fut::Ok, Fut::Error>;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        unsafe { self.map_unchecked_mut(|this| &mut this.future) }
+            .try_poll(waker)
+    }
+}
+
+/// Future for the [`and_then`](TryFutureExt::and_then) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct AndThen<Fut1, Fut2, F> {
+    chain: TryChain<Fut1, Fut2, F>,
+}
+
+impl<Waker, Fut1, Fut2, F> Future<Waker> for AndThen<Fut1, Fut2, F>
+where
+    Fut1: TryFuture<Waker>,
+    Fut2: TryFuture<Waker, Error = Fut1::Error>,
+    F: FnOnce(Fut1::Ok) -> Fut2,
+{
+    type Output = Result<Fut2::Ok, Fut2::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        unsafe { self.map_unchecked_mut(|this| &mut this.chain) }.poll(
+            waker,
+            |output, f| match output {
+                Ok(ok) => TryChainAction::Future(f(ok)),
+                Err(err) => TryChainAction::Output(Err(err)),
+            },
+        )
+    }
+}
+
+/// Future for the [`or_else`](TryFutureExt::or_else) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct OrElse<Fut1, Fut2, F> {
+    chain: TryChain<Fut1, Fut2, F>,
+}
+
+impl<Waker, Fut1, Fut2, F> Future<Waker> for OrElse<Fut1, Fut2, F>
+where
+    Fut1: TryFuture<Waker>,
+    Fut2: TryFuture<Waker, Ok = Fut1::Ok>,
+    F: FnOnce(Fut1::Error) -> Fut2,
+{
+    type Output = Result<Fut2::Ok, Fut2::Error>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        unsafe { self.map_unchecked_mut(|this| &mut this.chain) }.poll(
+            waker,
+            |output, f| match output {
+                Ok(ok) => TryChainAction::Output(Ok(ok)),
+                Err(err) => TryChainAction::Future(f(err)),
+            },
+        )
+    }
+}
+
+/// Runs `Fut1`, then possibly the `Fut2` created from its output by `F`.
+enum TryChain<Fut1, Fut2, F> {
+    First(/* #[pin] */ Fut1, Option<F>),
+    Second(/* #[pin] */ Fut2),
+    Empty,
+}
+
+/// What to do with the output of the first future of a [`TryChain`].
+enum TryChainAction<T, E, Fut2> {
+    Future(Fut2),
+    Output(Result<T, E>),
+}
+
+impl<Fut1, Fut2, F> TryChain<Fut1, Fut2, F> {
+    fn poll<Waker>(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+        f: impl FnOnce(
+            Result<Fut1::Ok, Fut1::Error>,
+            F,
+        ) -> TryChainAction<Fut2::Ok, Fut2::Error, Fut2>,
+    ) -> Poll<Result<Fut2::Ok, Fut2::Error>>
+    where
+        Fut1: TryFuture<Waker>,
+        Fut2: TryFuture<Waker>,
+    {
+        let mut f = Some(f);
+        loop {
+            let output = match unsafe { self.as_mut().get_unchecked_mut() } {
+                Self::First(future, data) => {
+                    let output = ready!(
+                        unsafe { Pin::new_unchecked(future) }.try_poll(waker)
+                    );
+                    let data = data.take().expect("TryChain data taken");
+                    let f = f.take().expect("TryChain polled first twice");
+                    match f(output, data) {
+                        TryChainAction::Future(future) => {
+                            self.set(Self::Second(future));
+                            continue;
+                        }
+                        TryChainAction::Output(output) => output,
+                    }
+                }
+                Self::Second(future) => ready!(
+                    unsafe { Pin::new_unchecked(future) }.try_poll(waker)
+                ),
+                Self::Empty => panic!("future polled after completion"),
+            };
+            self.set(Self::Empty);
+            return Poll::Ready(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin::pin;
+
+    use super::*;
+    use crate::{block_on::block_on, poll_fn};
+
+    fn ready<T>(value: T) -> impl Future<crate::LocalWaker, Output = T> {
+        let mut value = Some(value);
+        poll_fn(move |_| Poll::Ready(value.take().unwrap()))
+    }
+
+    #[test]
+    fn map() {
+        let future = pin!(ready(Ok::<_, ()>(1)).map_ok(|x| x + 1));
+        assert_eq!(block_on(future), Ok(2));
+
+        let future = pin!(ready(Err::<(), _>(1)).map_err(|x| x + 1));
+        assert_eq!(block_on(future), Err(2));
+    }
+
+    #[test]
+    fn and_then() {
+        let future = pin!(ready(Ok::<_, ()>(1)).and_then(|x| ready(Ok(x * 3))));
+        assert_eq!(block_on(future), Ok(3));
+
+        // errors short circuit
+        let mut called = false;
+        let future = pin!(ready(Err::<i32, _>(1)).and_then(|x| {
+            called = true;
+            ready(Ok(x))
+        }));
+        assert_eq!(block_on(future), Err(1));
+        assert!(!called);
+    }
+
+    #[test]
+    fn or_else() {
+        let future = pin!(ready(Err::<i32, _>(1)).or_else(|e| ready(Ok(e))));
+        assert_eq!(block_on(future), Ok::<_, ()>(1));
+    }
+
+    #[test]
+    fn inspect_err() {
+        let mut seen = None;
+        let future = pin!(
+            ready(Err::<(), _>(4))
+                .inspect_err(|e| seen = Some(*e))
+                .into_future()
+        );
+        assert_eq!(block_on(future), Err(4));
+        assert_eq!(seen, Some(4));
+    }
+}
@@ -0,0 +1,351 @@
+//! Intrusive queue of tasks waiting on an event source.
+//!
+//! A [`WaitQueue`] is owned by an event source, such as a channel, mutex or
+//! timer, and any number of pinned [`Waiter`]s, usually living on the stack
+//! inside of the futures waiting on it, link themselves into it. Like the
+//! guards of `lifetime-guard`, both sides unlink each other when dropped, so
+//! no allocation or reference counting is required.
+
+use core::{
+    cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull, task::Poll,
+};
+
+use futures_core::{Future, WakeGuard, WakerRegistration, WakerSlot};
+
+/// FIFO queue of [`Waiter`]s, owned by an event source.
+///
+/// Dropping the queue wakes every waiter still in it, since the event they
+/// are waiting for can no longer happen.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct WaitQueue<Waker: WakeGuard> {
+    head: Cell<Option<NonNull<Waiter<Waker>>>>,
+    tail: Cell<Option<NonNull<Waiter<Waker>>>>,
+    _marker: PhantomPinned,
+}
+
+impl<Waker: WakeGuard> WaitQueue<Waker> {
+    /// Creates a new, empty `WaitQueue`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            head: Cell::new(None),
+            tail: Cell::new(None),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Returns `true` if no waiters are in the queue.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.head.get().is_none()
+    }
+
+    /// Removes the waiter that has been waiting the longest from the queue
+    /// and wakes it, returning `false` if the queue is empty.
+    pub fn wake_one(&self) -> bool {
+        let Some(head) = self.head.get() else {
+            return false;
+        };
+        // linked waiters are valid until they unlink themselves
+        let waiter = unsafe { head.as_ref() };
+        self.unlink(waiter);
+        waiter.woken.set(true);
+        waiter.slot.wake();
+        true
+    }
+
+    /// Removes every waiter from the queue and wakes them, returning how
+    /// many were woken.
+    pub fn wake_all(&self) -> usize {
+        let mut woken = 0;
+        while self.wake_one() {
+            woken += 1;
+        }
+        woken
+    }
+
+    /// Returns a future that completes once it is woken through `self`.
+    #[inline]
+    pub fn wait(self: Pin<&Self>) -> Wait<'_, Waker> {
+        Wait {
+            queue: self,
+            waiter: Waiter::new(),
+        }
+    }
+
+    fn push_back(self: Pin<&Self>, waiter: Pin<&Waiter<Waker>>) {
+        let ptr = NonNull::from(waiter.get_ref());
+        waiter.prev.set(self.tail.get());
+        waiter.next.set(None);
+        match self.tail.get() {
+            Some(tail) => unsafe { tail.as_ref() }.next.set(Some(ptr)),
+            None => self.head.set(Some(ptr)),
+        }
+        self.tail.set(Some(ptr));
+        waiter.queue.set(Some(NonNull::from(self.get_ref())));
+    }
+
+    /// Unlinks `waiter`, which must be linked into `self`.
+    fn unlink(&self, waiter: &Waiter<Waker>) {
+        let prev = waiter.prev.take();
+        let next = waiter.next.take();
+        match prev {
+            Some(prev) => unsafe { prev.as_ref() }.next.set(next),
+            None => self.head.set(next),
+        }
+        match next {
+            Some(next) => unsafe { next.as_ref() }.prev.set(prev),
+            None => self.tail.set(prev),
+        }
+        waiter.queue.set(None);
+    }
+}
+
+impl<Waker: WakeGuard> Default for WaitQueue<Waker> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Waker: WakeGuard> Drop for WaitQueue<Waker> {
+    #[inline]
+    fn drop(&mut self) {
+        self.wake_all();
+    }
+}
+
+/// Node linked into a [`WaitQueue`] by a task waiting on it.
+///
+/// A waiter wakes its task through a [`WakerSlot`], so, as with any slot,
+/// only the most recently registered of several waiters polled with the
+/// same waker is woken. Futures waiting on several queues at once should
+/// poll each waiter with its own waker, as `Join` and `Race` do.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct Waiter<Waker: WakeGuard> {
+    slot: WakerSlot<Waker>,
+    /// The queue `self` is linked into, if any.
+    queue: Cell<Option<NonNull<WaitQueue<Waker>>>>,
+    prev: Cell<Option<NonNull<Waiter<Waker>>>>,
+    next: Cell<Option<NonNull<Waiter<Waker>>>>,
+    /// Set when `self` is removed from its queue by a wake.
+    woken: Cell<bool>,
+    _marker: PhantomPinned,
+}
+
+impl<Waker: WakeGuard> Waiter<Waker> {
+    /// Creates a new `Waiter` that isn't in any queue.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            slot: WakerSlot::new(),
+            queue: Cell::new(None),
+            prev: Cell::new(None),
+            next: Cell::new(None),
+            woken: Cell::new(false),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Returns `true` if `self` is currently in a queue.
+    #[inline]
+    pub fn is_queued(&self) -> bool {
+        self.queue.get().is_some()
+    }
+
+    /// Returns `Poll::Ready` if `self` was woken since it last returned
+    /// `Poll::Ready`, and otherwise makes sure `self` is at the back of
+    /// `queue` and will wake the task polled with `waker`.
+    ///
+    /// A waiter that is already in `queue` keeps its place, and one that is
+    /// in a different queue is moved to `queue`.
+    pub fn poll_wait(
+        self: Pin<&Self>,
+        queue: Pin<&WaitQueue<Waker>>,
+        waker: Pin<&Waker>,
+    ) -> Poll<()> {
+        if self.woken.take() {
+            return Poll::Ready(());
+        }
+
+        match self.queue.get() {
+            Some(current) if current == NonNull::from(queue.get_ref()) => {}
+            Some(current) => {
+                unsafe { current.as_ref() }.unlink(&self);
+                queue.push_back(self);
+            }
+            None => queue.push_back(self),
+        }
+        WakerRegistration::new(waker)
+            .register(unsafe { self.map_unchecked(|this| &this.slot) });
+        Poll::Pending
+    }
+
+    /// Removes `self` from the queue it is in, if any.
+    #[inline]
+    pub fn cancel(&self) {
+        if let Some(queue) = self.queue.get() {
+            unsafe { queue.as_ref() }.unlink(self);
+        }
+    }
+}
+
+impl<Waker: WakeGuard> Default for Waiter<Waker> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Waker: WakeGuard> Drop for Waiter<Waker> {
+    #[inline]
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Future for the [`wait`](WaitQueue::wait) method.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Wait<'a, Waker: WakeGuard> {
+    queue: Pin<&'a WaitQueue<Waker>>,
+    /* #[pin] */
+    waiter: Waiter<Waker>,
+}
+
+impl<Waker: WakeGuard> Future<Waker> for Wait<'_, Waker> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        unsafe { Pin::new_unchecked(&this.waiter) }.poll_wait(this.queue, waker)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin::pin;
+
+    use futures_core::test_util::{Counter, waker};
+
+    use super::*;
+    use crate::LocalWaker;
+
+    #[test]
+    fn wake_one() {
+        let counters = [const { Counter::new() }; 3];
+        let queue = pin!(WaitQueue::new());
+        let queue = queue.into_ref();
+        let wakers = counters
+            .each_ref()
+            .map(|counter| unsafe { waker::<LocalWaker>(counter) });
+        let wakers = pin!(wakers);
+        let waiters = pin!(core::array::from_fn::<_, 3, _>(|_| Waiter::new()));
+
+        let waiter = |index: usize| unsafe {
+            waiters.as_ref().map_unchecked(|waiters| &waiters[index])
+        };
+        let waker = |index: usize| unsafe {
+            wakers.as_ref().map_unchecked(|wakers| &wakers[index])
+        };
+
+        for index in 0..3 {
+            let poll = waiter(index).poll_wait(queue, waker(index));
+            assert_eq!(poll, Poll::Pending);
+        }
+        // polling again keeps the waiter's place
+        assert_eq!(waiter(0).poll_wait(queue, waker(0)), Poll::Pending);
+
+        assert!(queue.wake_one());
+        assert_eq!(counters.each_ref().map(Counter::count), [1, 0, 0]);
+        assert!(!waiter(0).is_queued());
+        assert_eq!(waiter(0).poll_wait(queue, waker(0)), Poll::Ready(()));
+
+        assert!(queue.wake_one());
+        assert_eq!(counters.each_ref().map(Counter::count), [1, 1, 0]);
+
+        assert_eq!(queue.wake_all(), 1);
+        assert_eq!(counters.each_ref().map(Counter::count), [1, 1, 1]);
+        assert!(queue.is_empty());
+        assert!(!queue.wake_one());
+    }
+
+    #[test]
+    fn waiter_dropped() {
+        let counter = Counter::new();
+        let waker = pin!(unsafe { waker::<LocalWaker>(&counter) });
+        let queue = pin!(WaitQueue::new());
+        let queue = queue.into_ref();
+
+        let first = pin!(Waiter::new());
+        assert_eq!(
+            first.as_ref().poll_wait(queue, waker.as_ref()),
+            Poll::Pending
+        );
+        {
+            let second = pin!(Waiter::new());
+            let poll = second.as_ref().poll_wait(queue, waker.as_ref());
+            assert_eq!(poll, Poll::Pending);
+            first.cancel();
+        }
+
+        // both waiters unlinked themselves
+        assert!(queue.is_empty());
+        assert_eq!(queue.wake_all(), 0);
+        assert_eq!(counter.count(), 0);
+    }
+
+    #[test]
+    fn queue_dropped() {
+        let counter = Counter::new();
+        let waker = pin!(unsafe { waker::<LocalWaker>(&counter) });
+        let waiter = pin!(Waiter::new());
+        {
+            let queue = pin!(WaitQueue::new());
+            let poll =
+                waiter.as_ref().poll_wait(queue.as_ref(), waker.as_ref());
+            assert_eq!(poll, Poll::Pending);
+        }
+
+        // the queue woke the waiter when it was dropped
+        assert_eq!(counter.count(), 1);
+        assert!(!waiter.is_queued());
+    }
+
+    #[test]
+    fn wait() {
+        let counter = Counter::new();
+        let waker = pin!(unsafe { waker::<LocalWaker>(&counter) });
+        let queue = pin!(WaitQueue::new());
+        let queue = queue.into_ref();
+
+        let mut wait = pin!(queue.wait());
+        assert_eq!(wait.as_mut().poll(waker.as_ref()), Poll::Pending);
+        assert!(!queue.is_empty());
+
+        queue.wake_one();
+        assert_eq!(counter.count(), 1);
+        assert_eq!(wait.poll(waker.as_ref()), Poll::Ready(()));
+    }
+}
@@ -0,0 +1,421 @@
+use core::{
+    array,
+    cell::{Cell, RefCell},
+    marker::PhantomPinned,
+    pin::Pin,
+    ptr::NonNull,
+};
+
+/// Strong guard for granting read access to `N` interior mutable values, each
+/// to its own [`SlotRefGuard`](SlotRefGuard).
+///
+/// This behaves like an array of [`ValueGuard`](crate::guard::ValueGuard)s,
+/// where each slot can be referenced by a different `SlotRefGuard`. A
+/// bitmask tracks which slots are referenced, so dropping `Self` takes
+/// constant time when none are, and otherwise only touches the referenced
+/// slots rather than all `N`.
+///
+/// `N` may be at most 64.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct ValueGuardArray<T, const N: usize> {
+    /// Contains the values being immutably accessed by the `SlotRefGuard`s
+    /// and mutably accessed by `Self`.
+    data: [RefCell<T>; N],
+    /// Pointers to the `SlotRefGuard` with read access to each slot of
+    /// `data`, to invalidate them when `Self` is dropped.
+    ref_guards: [Cell<Option<NonNull<SlotRefGuard<T, N>>>>; N],
+    /// Bit `i` is set if `ref_guards[i]` is `Some`.
+    valid: Cell<u64>,
+    _marker: PhantomPinned,
+}
+
+impl<T, const N: usize> ValueGuardArray<T, N> {
+    /// Creates a new `ValueGuardArray` containing `data`.
+    #[inline]
+    pub fn new(data: [T; N]) -> Self {
+        const { assert!(N <= 64, "ValueGuardArray supports at most 64 slots") };
+        Self {
+            data: data.map(RefCell::new),
+            ref_guards: array::from_fn(|_| Cell::new(None)),
+            valid: Cell::new(0),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Creates a new `ValueGuardArray` where each slot contains the result
+    /// of calling `f` with its index.
+    #[inline]
+    pub fn from_fn(f: impl FnMut(usize) -> T) -> Self {
+        Self::new(array::from_fn(f))
+    }
+
+    /// Sets the value stored in slot `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, or the value is currently
+    /// borrowed by [`Self::with`] or [`SlotRefGuard::with`].
+    #[inline]
+    pub fn set(&self, index: usize, value: T) {
+        self.replace(index, value);
+    }
+
+    /// Replaces the value stored in slot `index`, returning the old value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds, or the value is currently
+    /// borrowed by [`Self::with`] or [`SlotRefGuard::with`].
+    #[inline]
+    pub fn replace(&self, index: usize, value: T) -> T {
+        self.data[index].replace(value)
+    }
+
+    /// Calls `f` with a reference to the value stored in slot `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn with<R>(&self, index: usize, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data[index].borrow())
+    }
+
+    /// Returns `true` if slot `index` is referenced by a `SlotRefGuard`.
+    #[inline]
+    pub fn has_ref(&self, index: usize) -> bool {
+        self.valid.get() & bit(index) != 0
+    }
+
+    /// Returns an iterator over the indices of the slots referenced by a
+    /// `SlotRefGuard`, in ascending order.
+    #[inline]
+    pub fn registered(&self) -> impl Iterator<Item = usize> + use<T, N> {
+        bits(self.valid.get())
+    }
+
+    /// Invalidates the `SlotRefGuard`s referencing any slot of `self`.
+    #[inline]
+    pub fn unregister_all(&self) {
+        for index in bits(self.valid.replace(0)) {
+            if let Some(guard) = self.ref_guards[index].take() {
+                unsafe { (*guard.as_ptr()).invalidate_value_guard() };
+            }
+        }
+    }
+
+    #[inline]
+    fn invalidate_ref_guard(&self, index: usize) {
+        self.ref_guards[index].set(None);
+        self.valid.set(self.valid.get() & !bit(index));
+    }
+
+    #[inline]
+    fn replace_ref_guard(
+        &self,
+        index: usize,
+        ref_guard: NonNull<SlotRefGuard<T, N>>,
+    ) {
+        if let Some(guard) = self.ref_guards[index].replace(Some(ref_guard)) {
+            unsafe { (*guard.as_ptr()).invalidate_value_guard() };
+        }
+        self.valid.set(self.valid.get() | bit(index));
+    }
+}
+
+impl<T: Copy, const N: usize> ValueGuardArray<T, N> {
+    /// Gets a copy of the value stored in slot `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn get(&self, index: usize) -> T {
+        *self.data[index].borrow()
+    }
+}
+
+impl<T, const N: usize> Drop for ValueGuardArray<T, N> {
+    #[inline]
+    fn drop(&mut self) {
+        // only referenced slots can be borrowed by `SlotRefGuard::with`
+        if bits(self.valid.get())
+            .any(|index| self.data[index].try_borrow_mut().is_err())
+        {
+            crate::abort(
+                "ValueGuardArray dropped while one of its values was borrowed",
+            );
+        }
+        self.unregister_all();
+    }
+}
+
+/// Weak guard for acquiring read only access to a single slot of a
+/// `ValueGuardArray`.
+///
+/// Provides [`SlotRefGuard::register()`](Self::register) to reference a
+/// slot of a `ValueGuardArray`. Like a `RefGuard`, each slot can be
+/// referenced by at most one `SlotRefGuard` at a time.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct SlotRefGuard<T, const N: usize> {
+    value_guard: Cell<Option<NonNull<ValueGuardArray<T, N>>>>,
+    /// The slot of `value_guard` referenced by `Self`.
+    index: Cell<usize>,
+    _marker: PhantomPinned,
+}
+
+impl<T, const N: usize> SlotRefGuard<T, N> {
+    /// Creates a new `SlotRefGuard` with no reference to a
+    /// `ValueGuardArray`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            value_guard: Cell::new(None),
+            index: Cell::new(0),
+            _marker: PhantomPinned,
+        }
+    }
+
+    #[inline]
+    fn invalidate_value_guard(&self) {
+        self.value_guard.set(None);
+    }
+
+    #[inline]
+    fn value_guard(&self) -> Option<&ValueGuardArray<T, N>> {
+        self.value_guard
+            .get()
+            .map(|guard| unsafe { &*guard.as_ptr() })
+    }
+
+    /// Binds slot `index` of a pinned `value_guard` to `self`.
+    ///
+    /// If `self` references another slot, or slot `index` is referenced by
+    /// a different `SlotRefGuard`, those references are invalidated first, as
+    /// with [`RefGuard::register()`](crate::guard::RefGuard::register).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    #[inline]
+    pub fn register<'a>(
+        self: Pin<&'a SlotRefGuard<T, N>>,
+        value_guard: Pin<&'a ValueGuardArray<T, N>>,
+        index: usize,
+    ) {
+        assert!(index < N, "index out of bounds");
+        let value_guard = value_guard.get_ref();
+        let this = NonNull::from(self.get_ref());
+        if value_guard.ref_guards[index].get() == Some(this) {
+            return;
+        }
+        self.unregister();
+        value_guard.replace_ref_guard(index, this);
+        self.value_guard.set(Some(value_guard.into()));
+        self.index.set(index);
+    }
+
+    /// Unbinds `self` from the slot it references, if any.
+    #[inline]
+    pub fn unregister(&self) {
+        if let Some(value_guard) = self.value_guard.take() {
+            unsafe {
+                (*value_guard.as_ptr()).invalidate_ref_guard(self.index.get())
+            };
+        }
+    }
+
+    /// Returns `true` if `self` currently references a slot of a
+    /// `ValueGuardArray`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.value_guard.get().is_some()
+    }
+
+    /// Returns the index of the slot `self` currently references, if any.
+    #[inline]
+    pub fn index(&self) -> Option<usize> {
+        self.is_registered().then(|| self.index.get())
+    }
+
+    /// Calls `f` with a reference to the value stored in the slot this
+    /// `SlotRefGuard` references, returning `None` if there is no such slot.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.value_guard()
+            .map(|guard| f(&guard.data[self.index.get()].borrow()))
+    }
+}
+
+impl<T: Copy, const N: usize> SlotRefGuard<T, N> {
+    /// Gets a copy of the value stored in the slot this `SlotRefGuard`
+    /// references.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.with(|value| *value)
+    }
+}
+
+impl<T, const N: usize> Drop for SlotRefGuard<T, N> {
+    #[inline]
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+impl<T, const N: usize> Default for SlotRefGuard<T, N> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the mask for slot `index`, or `0` if it is out of range.
+#[inline]
+fn bit(index: usize) -> u64 {
+    1u64.checked_shl(index as u32).unwrap_or(0)
+}
+
+/// Iterates over the indices of the set bits of `mask`.
+#[inline]
+fn bits(mut mask: u64) -> impl Iterator<Item = usize> {
+    core::iter::from_fn(move || {
+        (mask != 0).then(|| {
+            let index = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            index
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use core::{mem, pin};
+
+    extern crate alloc;
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let weak1 = pin::pin!(SlotRefGuard::new());
+        let weak3 = pin::pin!(SlotRefGuard::new());
+        {
+            let strong = pin::pin!(ValueGuardArray::new([1, 2, 3, 4]));
+            weak1.as_ref().register(strong.as_ref(), 1);
+            weak3.as_ref().register(strong.as_ref(), 3);
+
+            assert!(strong.has_ref(1));
+            assert!(!strong.has_ref(0));
+            assert_eq!(weak1.get(), Some(2));
+            assert_eq!(weak3.index(), Some(3));
+
+            strong.as_ref().set(3, 5);
+            assert_eq!(weak3.get(), Some(5));
+            assert_eq!(strong.get(3), 5);
+            assert_eq!(weak1.with(|value| value * 2), Some(4));
+            assert!(strong.registered().eq([1, 3]));
+
+            weak1.unregister();
+            assert_eq!(weak1.get(), None);
+            assert!(strong.registered().eq([3]));
+        }
+        assert!(!weak3.is_registered());
+        assert_eq!(weak3.get(), None);
+        assert_eq!(weak3.index(), None);
+    }
+
+    #[test]
+    fn unregister_all() {
+        let strong = pin::pin!(ValueGuardArray::<_, 3>::from_fn(|i| i));
+        let weak =
+            pin::pin!(array::from_fn::<_, 3, _>(|_| SlotRefGuard::new()));
+        let weak = |index: usize| unsafe {
+            weak.as_ref().map_unchecked(|weak| &weak[index])
+        };
+        for index in 0..3 {
+            weak(index).register(strong.as_ref(), index);
+        }
+        assert_eq!(weak(2).get(), Some(2));
+
+        strong.unregister_all();
+        assert_eq!(strong.registered().count(), 0);
+        assert!((0..3).all(|index| !weak(index).is_registered()));
+    }
+
+    #[test]
+    fn drop_ref_guard() {
+        let strong = pin::pin!(ValueGuardArray::new([1, 2]));
+        {
+            let weak = pin::pin!(SlotRefGuard::new());
+            weak.as_ref().register(strong.as_ref(), 0);
+            assert!(strong.has_ref(0));
+        }
+        assert!(!strong.has_ref(0));
+        strong.as_ref().set(0, 3);
+        assert_eq!(strong.get(0), 3);
+    }
+
+    #[test]
+    fn reregister() {
+        let weak = pin::pin!(SlotRefGuard::new());
+        let strong1 = pin::pin!(ValueGuardArray::new([1, 2]));
+        let strong2 = pin::pin!(ValueGuardArray::new([3, 4]));
+        weak.as_ref().register(strong1.as_ref(), 0);
+
+        // moving to another slot invalidates the first one
+        weak.as_ref().register(strong2.as_ref(), 1);
+        assert!(!strong1.has_ref(0));
+        assert_eq!(weak.get(), Some(4));
+
+        // a slot is referenced by at most one guard
+        let weak2 = pin::pin!(SlotRefGuard::new());
+        weak2.as_ref().register(strong2.as_ref(), 1);
+        assert_eq!(weak.get(), None);
+        assert_eq!(weak2.get(), Some(4));
+        assert!(strong2.registered().eq([1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn register_out_of_bounds() {
+        let strong = pin::pin!(ValueGuardArray::new([1, 2]));
+        let weak = pin::pin!(SlotRefGuard::new());
+        weak.as_ref().register(strong.as_ref(), 2);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn safe_leak() {
+        let strong = alloc::boxed::Box::pin(ValueGuardArray::new([10, 20]));
+        let weak = pin::pin!(SlotRefGuard::new());
+        weak.as_ref().register(strong.as_ref(), 1);
+
+        // strong is now on the heap and will never be freed
+        mem::forget(strong);
+
+        assert_eq!(weak.get(), Some(20));
+    }
+}
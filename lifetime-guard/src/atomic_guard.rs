@@ -1,6 +1,11 @@
-use core::{cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull};
+use core::{cell::RefCell, marker::PhantomPinned, pin::Pin, ptr::NonNull};
 
-use critical_section::Mutex;
+use futures_core::{SyncWakePtr, WakeGuard};
+
+use crate::sync::{
+    Cell,
+    critical_section::{self, CriticalSection, Mutex},
+};
 
 struct RawValueGuard<T> {
     /// Contains the value being immutably accessed by `RefGuard` and
@@ -9,7 +14,11 @@ struct RawValueGuard<T> {
     /// This needs to be a cell so that the original immutable alias
     /// to `Self` (given to `RefGuard`) can continue to be referenced after
     /// invalidated by the creation of a mutable alias for `Self::set`.
-    data: Cell<T>,
+    ///
+    /// It is a `RefCell` rather than a `Cell` so that non-`Copy` values can
+    /// be borrowed by [`AtomicRefGuard::with`], which causes any attempt to
+    /// mutate the value from inside the closure to panic.
+    data: RefCell<T>,
     /// A pointer to a `RefGuard` with read access to `data` to invalidate that
     /// `RefGuard` when `Self` is dropped.
     ref_guard: Cell<Option<NonNull<AtomicRefGuard<T>>>>,
@@ -47,7 +56,7 @@ impl<T> AtomicValueGuard<T> {
     pub fn new(data: T) -> Self {
         Self {
             mutex: Mutex::new(RawValueGuard {
-                data: Cell::new(data),
+                data: RefCell::new(data),
                 ref_guard: Cell::new(None),
             }),
             _marker: PhantomPinned,
@@ -55,24 +64,88 @@ impl<T> AtomicValueGuard<T> {
     }
 
     /// Sets the internal value stored by `Self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicRefGuard::with`] on the current thread.
     #[inline]
     pub fn set(&self, value: T) {
-        critical_section::with(|cs| self.mutex.borrow(cs).data.set(value));
+        // the old value is dropped outside of the critical section
+        self.replace(value);
+    }
+
+    /// Replaces the internal value stored by `Self`, returning the old value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicRefGuard::with`] on the current thread.
+    #[inline]
+    pub fn replace(&self, value: T) -> T {
+        critical_section::with(|cs| self.mutex.borrow(cs).data.replace(value))
     }
 
+    /// Calls `f` with a reference to the internal value stored by `Self`.
+    ///
+    /// `f` runs inside of a critical section, so it should return quickly.
     #[inline]
-    fn invalidate_ref_guard(&self) {
-        critical_section::with(|cs| self.mutex.borrow(cs).ref_guard.set(None));
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        critical_section::with(|cs| f(&self.mutex.borrow(cs).data.borrow()))
     }
 
+    /// Returns `true` if a `RefGuard` is currently registered to `self`.
     #[inline]
-    fn replace_ref_guard(&self, ref_guard: Option<NonNull<AtomicRefGuard<T>>>) {
+    pub fn has_ref(&self) -> bool {
         critical_section::with(|cs| {
-            if let Some(guard) =
-                self.mutex.borrow(cs).ref_guard.replace(ref_guard)
-            {
-                unsafe { (*guard.as_ptr()).invalidate_value_guard() }
-            }
+            self.mutex.borrow(cs).ref_guard.get().is_some()
+        })
+    }
+
+    #[inline]
+    fn replace_ref_guard(
+        &self,
+        cs: CriticalSection<'_>,
+        ref_guard: Option<NonNull<AtomicRefGuard<T>>>,
+    ) {
+        if let Some(guard) = self.mutex.borrow(cs).ref_guard.replace(ref_guard)
+        {
+            unsafe { (*guard.as_ptr()).value_guard.borrow(cs).set(None) }
+        }
+    }
+}
+
+impl<T: Default> AtomicValueGuard<T> {
+    /// Takes the internal value stored by `Self`, leaving `T::default()` in
+    /// its place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicRefGuard::with`] on the current thread.
+    #[inline]
+    pub fn take(&self) -> T {
+        self.replace(T::default())
+    }
+
+    /// Updates the internal value stored by `Self` using `f`.
+    ///
+    /// `f` runs inside of a single critical section, so it should return
+    /// quickly, but concurrent updates are never lost and other threads never
+    /// observe the value being updated. The value is taken out of `Self` for
+    /// the duration of `f`, so reading it from inside `f` will observe
+    /// `T::default()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicRefGuard::with`] on the current thread.
+    #[inline]
+    pub fn update(&self, f: impl FnOnce(T) -> T) {
+        critical_section::with(|cs| {
+            let data = &self.mutex.borrow(cs).data;
+            let value = f(data.take());
+            data.replace(value);
         });
     }
 }
@@ -81,17 +154,37 @@ impl<T: Copy> AtomicValueGuard<T> {
     /// Gets a copy of the value stored inside this `ValueGuard`.
     #[inline]
     pub fn get(&self) -> T {
-        critical_section::with(|cs| self.mutex.borrow(cs).data.get())
+        self.with(|value| *value)
     }
 }
 
 impl<T> Drop for AtomicValueGuard<T> {
     #[inline]
     fn drop(&mut self) {
-        self.replace_ref_guard(None);
+        critical_section::with(|cs| {
+            if self.mutex.borrow(cs).data.try_borrow_mut().is_err() {
+                // `Self` is being dropped from inside of
+                // `AtomicRefGuard::with`, which would free the value while it
+                // is still borrowed
+                crate::abort(
+                    "AtomicValueGuard dropped while its value was borrowed",
+                );
+            }
+            self.replace_ref_guard(cs, None);
+        });
     }
 }
 
+// SAFETY: the pointer to the `AtomicRefGuard` is only accessed inside of a
+// critical section, and `T` may be dropped or replaced from any thread that
+// owns or references `Self`, which requires `T: Send`.
+unsafe impl<T: Send> Send for AtomicValueGuard<T> {}
+
+// SAFETY: every access to `T` and the pointer to the `AtomicRefGuard` happens
+// inside of a critical section, so like `Mutex<T>`, shared references only
+// require `T: Send`.
+unsafe impl<T: Send> Sync for AtomicValueGuard<T> {}
+
 /// Weak guard for acquiring read only access to a `ValueGuard`'s value.
 ///
 /// Provides [`WeakGuard::register()`](Self::register) to register a `ValueGuard`
@@ -109,7 +202,10 @@ impl<T> Drop for AtomicValueGuard<T> {
 /// `Box::leak()` because heap allocated data will never be overwritten if it
 /// is never freed.
 pub struct AtomicRefGuard<T> {
-    value_guard: Cell<Option<NonNull<AtomicValueGuard<T>>>>,
+    /// Only accessible inside of a critical section, so that a concurrently
+    /// dropping `AtomicValueGuard` can't be dereferenced after invalidating
+    /// it.
+    value_guard: Mutex<Cell<Option<NonNull<AtomicValueGuard<T>>>>>,
     _marker: PhantomPinned,
 }
 
@@ -118,23 +214,19 @@ impl<T> AtomicRefGuard<T> {
     #[inline]
     pub fn new() -> Self {
         Self {
-            value_guard: Cell::new(None),
+            value_guard: Mutex::new(Cell::new(None)),
             _marker: PhantomPinned,
         }
     }
 
-    #[inline]
-    fn invalidate_value_guard(&self) {
-        self.value_guard.set(None);
-    }
-
     #[inline]
     fn replace_value_guard(
         &self,
+        cs: CriticalSection<'_>,
         value_guard: Option<NonNull<AtomicValueGuard<T>>>,
     ) {
-        if let Some(guard) = self.value_guard.replace(value_guard) {
-            unsafe { (*guard.as_ptr()).invalidate_ref_guard() }
+        if let Some(guard) = self.value_guard.borrow(cs).replace(value_guard) {
+            unsafe { (*guard.as_ptr()).mutex.borrow(cs).ref_guard.set(None) }
         }
     }
 
@@ -151,8 +243,55 @@ impl<T> AtomicRefGuard<T> {
         self: Pin<&'a AtomicRefGuard<T>>,
         value_guard: Pin<&'a AtomicValueGuard<T>>,
     ) {
-        value_guard.replace_ref_guard(Some(self.get_ref().into()));
-        self.replace_value_guard(Some(value_guard.get_ref().into()));
+        critical_section::with(|cs| {
+            value_guard.replace_ref_guard(cs, Some(self.get_ref().into()));
+            self.replace_value_guard(cs, Some(value_guard.get_ref().into()));
+        });
+    }
+
+    /// Unbinds `self` from the `ValueGuard` it references, if any.
+    ///
+    /// Both guards invalidate their references to each other, as if the
+    /// `ValueGuard` had been dropped.
+    #[inline]
+    pub fn unregister(&self) {
+        critical_section::with(|cs| self.replace_value_guard(cs, None));
+    }
+
+    /// Returns `true` if `self` currently references a `ValueGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        critical_section::with(|cs| self.value_guard.borrow(cs).get().is_some())
+    }
+
+    /// Returns the `ValueGuard` `self` references, if any, which can't be
+    /// dropped for the duration of `cs`.
+    #[inline]
+    pub(crate) fn value_guard<'cs>(
+        &'cs self,
+        cs: CriticalSection<'cs>,
+    ) -> Option<&'cs AtomicValueGuard<T>> {
+        self.value_guard
+            .borrow(cs)
+            .get()
+            .map(|guard| unsafe { &*guard.as_ptr() })
+    }
+}
+
+impl<T> AtomicRefGuard<T> {
+    /// Calls `f` with a reference to the value stored inside the
+    /// `ValueGuard` this `RefGuard` references, returning `None` if there is
+    /// no such `ValueGuard`.
+    ///
+    /// The reference can't escape `f`, so it can never outlive the
+    /// `ValueGuard`. `f` runs inside of a critical section, so it should
+    /// return quickly.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        critical_section::with(|cs| {
+            self.value_guard(cs)
+                .map(|guard| f(&guard.mutex.borrow(cs).data.borrow()))
+        })
     }
 }
 
@@ -161,16 +300,14 @@ impl<T: Copy> AtomicRefGuard<T> {
     /// references.
     #[inline]
     pub fn get(&self) -> Option<T> {
-        self.value_guard
-            .get()
-            .map(|guard| unsafe { (*guard.as_ptr()).get() })
+        self.with(|value| *value)
     }
 }
 
 impl<T> Drop for AtomicRefGuard<T> {
     #[inline]
     fn drop(&mut self) {
-        self.replace_value_guard(None);
+        critical_section::with(|cs| self.replace_value_guard(cs, None));
     }
 }
 
@@ -181,7 +318,48 @@ impl<T> Default for AtomicRefGuard<T> {
     }
 }
 
-#[cfg(test)]
+// SAFETY: the pointer to the `AtomicValueGuard` is only accessed inside of a
+// critical section, and the `AtomicValueGuard` is `Send` and `Sync` for
+// `T: Send`.
+unsafe impl<T: Send> Send for AtomicRefGuard<T> {}
+
+// SAFETY: every access through `&Self` happens inside of a critical section.
+unsafe impl<T: Send> Sync for AtomicRefGuard<T> {}
+
+impl WakeGuard for AtomicValueGuard<SyncWakePtr> {
+    type Parent = AtomicRefGuard<SyncWakePtr>;
+    type Ptr = SyncWakePtr;
+
+    #[inline]
+    fn new(wake: SyncWakePtr) -> Self {
+        Self::new(wake)
+    }
+
+    #[inline]
+    fn set(&self, wake: SyncWakePtr) {
+        self.set(wake);
+    }
+
+    #[inline]
+    fn wake(&self) {
+        // the task pointed to by a waker outlives the waker
+        unsafe { self.get().wake() }
+    }
+
+    #[inline]
+    fn register_parent<'a>(self: Pin<&'a Self>, parent: Pin<&'a Self::Parent>) {
+        parent.register(self);
+    }
+
+    #[inline]
+    fn wake_parent(parent: &Self::Parent) {
+        // wake inside of the critical section, so the waker can't be dropped
+        // by another thread between reading the pointer and waking it
+        parent.with(|wake| unsafe { wake.wake() });
+    }
+}
+
+#[cfg(all(test, not(loom)))]
 mod test {
     use core::{mem, pin};
 
@@ -225,7 +403,7 @@ mod test {
             // register next ptr, should invalidate previous weak ref (weak1)
             weak2.as_ref().register(strong.as_ref());
             assert_eq!(weak1.get(), None);
-            assert_eq!(weak1.value_guard.get(), None);
+            assert!(!weak1.is_registered());
 
             assert_eq!(strong.get(), 3);
             assert_eq!(weak2.get(), Some(3));
@@ -239,6 +417,75 @@ mod test {
         assert_eq!(weak2.get(), None);
     }
 
+    #[test]
+    fn unregister() {
+        let weak = pin::pin!(AtomicRefGuard::new());
+        let strong = pin::pin!(AtomicValueGuard::new(2));
+        assert!(!weak.is_registered());
+        assert!(!strong.has_ref());
+
+        weak.as_ref().register(strong.as_ref());
+        assert!(weak.is_registered());
+        assert!(strong.has_ref());
+
+        weak.unregister();
+        assert!(!weak.is_registered());
+        assert!(!strong.has_ref());
+        assert_eq!(weak.get(), None);
+
+        // unregistering twice is a no-op
+        weak.unregister();
+
+        weak.as_ref().register(strong.as_ref());
+        assert_eq!(weak.get(), Some(2));
+    }
+
+    #[test]
+    fn non_copy() {
+        #[derive(Debug, Default, PartialEq)]
+        struct Snapshot {
+            position: [i32; 3],
+            label: alloc::string::String,
+        }
+
+        let weak = pin::pin!(AtomicRefGuard::new());
+        {
+            let strong = pin::pin!(AtomicValueGuard::new(Snapshot {
+                position: [1, 2, 3],
+                label: "start".into(),
+            }));
+            weak.as_ref().register(strong.as_ref());
+
+            assert_eq!(weak.with(|s| s.position[1]), Some(2));
+            assert_eq!(strong.with(|s| s.label.len()), 5);
+
+            let old = strong.replace(Snapshot::default());
+            assert_eq!(old.label, "start");
+            assert_eq!(weak.with(|s| s.label.is_empty()), Some(true));
+
+            strong.update(|mut s| {
+                s.position[0] = 4;
+                s
+            });
+            assert_eq!(weak.with(|s| s.position), Some([4, 0, 0]));
+
+            assert_eq!(strong.take().position, [4, 0, 0]);
+            assert_eq!(weak.with(|s| s.position), Some([0, 0, 0]));
+        }
+
+        assert_eq!(weak.with(|s| s.position), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_while_borrowed() {
+        let weak = pin::pin!(AtomicRefGuard::new());
+        let strong = pin::pin!(AtomicValueGuard::new(1));
+        weak.as_ref().register(strong.as_ref());
+
+        weak.with(|_| strong.as_ref().set(2));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn safe_leak() {
@@ -253,3 +500,127 @@ mod test {
         assert_eq!(weak.get(), Some(10));
     }
 }
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    extern crate alloc;
+
+    use alloc::boxed::Box;
+    use loom::thread;
+
+    use super::*;
+
+    fn pair(
+        value: i32,
+    ) -> (
+        Pin<Box<AtomicValueGuard<i32>>>,
+        Pin<Box<AtomicRefGuard<i32>>>,
+    ) {
+        let strong = Box::pin(AtomicValueGuard::new(value));
+        let weak = Box::pin(AtomicRefGuard::new());
+        weak.as_ref().register(strong.as_ref());
+        (strong, weak)
+    }
+
+    #[test]
+    fn concurrent_drop() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(strong));
+            drop(weak);
+            handle.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn get_while_value_guard_drops() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(strong));
+            let value = weak.get();
+            assert!(value == Some(1) || value.is_none());
+            handle.join().unwrap();
+            assert_eq!(weak.get(), None);
+        });
+    }
+
+    #[test]
+    fn set_while_ref_guard_drops() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(weak));
+            strong.as_ref().set(2);
+            assert_eq!(strong.get(), 2);
+            handle.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn concurrent_get_and_set() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || {
+                let value = weak.get();
+                assert!(value == Some(1) || value == Some(2));
+                weak
+            });
+            strong.as_ref().set(2);
+            let weak = handle.join().unwrap();
+            assert_eq!(weak.get(), Some(2));
+        });
+    }
+
+    #[test]
+    fn register_while_value_guard_drops() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let other = Box::pin(AtomicValueGuard::new(2));
+            let handle = thread::spawn(move || drop(strong));
+            weak.as_ref().register(other.as_ref());
+            handle.join().unwrap();
+            assert_eq!(weak.get(), Some(2));
+            drop(other);
+            assert_eq!(weak.get(), None);
+        });
+    }
+
+    #[test]
+    fn unregister_while_value_guard_drops() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(strong));
+            weak.unregister();
+            assert!(!weak.is_registered());
+            handle.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn register_while_ref_guard_drops() {
+        loom::model(|| {
+            let (strong, weak) = pair(1);
+            let other = Box::pin(AtomicRefGuard::new());
+            let handle = thread::spawn(move || drop(weak));
+            other.as_ref().register(strong.as_ref());
+            handle.join().unwrap();
+            assert_eq!(other.get(), Some(1));
+            drop(strong);
+            assert_eq!(other.get(), None);
+        });
+    }
+
+    #[test]
+    fn concurrent_update() {
+        loom::model(|| {
+            let (strong, weak) = pair(0);
+            let strong = loom::sync::Arc::new(strong);
+            let handle = thread::spawn({
+                let strong = strong.clone();
+                move || strong.update(|value| value + 1)
+            });
+            strong.update(|value| value + 1);
+            handle.join().unwrap();
+            assert_eq!(weak.get(), Some(2));
+        });
+    }
+}
@@ -0,0 +1,310 @@
+use core::{marker::PhantomPinned, pin::Pin};
+
+use crate::{
+    atomic_guard::{AtomicRefGuard, AtomicValueGuard},
+    sync::critical_section,
+};
+
+/// Thread safe equivalent of
+/// [`SharedGuard`](crate::shared_guard::SharedGuard), built on
+/// [`AtomicValueGuard`].
+///
+/// Every operation on the value runs inside of a single critical section, so
+/// [`Self::compare_exchange`] is atomic with respect to the
+/// `AtomicSharedRefGuard`.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct AtomicSharedGuard<T> {
+    guard: AtomicValueGuard<T>,
+    _marker: PhantomPinned,
+}
+
+impl<T> AtomicSharedGuard<T> {
+    /// Creates a new `AtomicSharedGuard` containing `data`.
+    #[inline]
+    pub fn new(data: T) -> Self {
+        Self {
+            guard: AtomicValueGuard::new(data),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Sets the internal value stored by `Self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicSharedRefGuard::with`] on the current thread.
+    #[inline]
+    pub fn set(&self, value: T) {
+        self.guard.set(value);
+    }
+
+    /// Replaces the internal value stored by `Self`, returning the old value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicSharedRefGuard::with`] on the current thread.
+    #[inline]
+    pub fn swap(&self, value: T) -> T {
+        self.guard.replace(value)
+    }
+
+    /// Calls `f` with a reference to the internal value stored by `Self`.
+    ///
+    /// `f` runs inside of a critical section, so it should return quickly.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.guard.with(f)
+    }
+
+    /// Returns `true` if an `AtomicSharedRefGuard` is currently registered to
+    /// `self`.
+    #[inline]
+    pub fn has_ref(&self) -> bool {
+        self.guard.has_ref()
+    }
+}
+
+impl<T: Copy> AtomicSharedGuard<T> {
+    /// Gets a copy of the value stored inside this `AtomicSharedGuard`.
+    #[inline]
+    pub fn get(&self) -> T {
+        self.guard.get()
+    }
+}
+
+impl<T: Copy + PartialEq> AtomicSharedGuard<T> {
+    /// Replaces the internal value stored by `Self` with `new` if it is equal
+    /// to `current`.
+    ///
+    /// Returns the previous value in `Ok` if it was replaced, and in `Err`
+    /// otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicSharedRefGuard::with`] on the current thread.
+    #[inline]
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T> {
+        critical_section::with(|_| compare_exchange(&self.guard, current, new))
+    }
+}
+
+/// Thread safe equivalent of
+/// [`SharedRefGuard`](crate::shared_guard::SharedRefGuard), built on
+/// [`AtomicRefGuard`].
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct AtomicSharedRefGuard<T> {
+    guard: AtomicRefGuard<T>,
+    _marker: PhantomPinned,
+}
+
+impl<T> AtomicSharedRefGuard<T> {
+    /// Creates a new `AtomicSharedRefGuard` with no reference to an
+    /// `AtomicSharedGuard`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            guard: AtomicRefGuard::new(),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Binds a pinned `value_guard` to `self`.
+    ///
+    /// This behaves like [`AtomicRefGuard::register()`].
+    #[inline]
+    pub fn register<'a>(
+        self: Pin<&'a AtomicSharedRefGuard<T>>,
+        value_guard: Pin<&'a AtomicSharedGuard<T>>,
+    ) {
+        let guard = unsafe { self.map_unchecked(|this| &this.guard) };
+        guard
+            .register(unsafe { value_guard.map_unchecked(|this| &this.guard) });
+    }
+
+    /// Unbinds `self` from the `AtomicSharedGuard` it references, if any.
+    #[inline]
+    pub fn unregister(&self) {
+        self.guard.unregister();
+    }
+
+    /// Returns `true` if `self` currently references an
+    /// `AtomicSharedGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.guard.is_registered()
+    }
+
+    /// Calls `f` with a reference to the value stored inside the
+    /// `AtomicSharedGuard` this `AtomicSharedRefGuard` references, returning
+    /// `None` if there is no such `AtomicSharedGuard`.
+    ///
+    /// `f` runs inside of a critical section, so it should return quickly.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.guard.with(f)
+    }
+
+    /// Sets the value stored inside the `AtomicSharedGuard` this
+    /// `AtomicSharedRefGuard` references, returning `value` in `Err` if there
+    /// is no such `AtomicSharedGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicSharedGuard::with`] on the current thread.
+    #[inline]
+    pub fn set(&self, value: T) -> Result<(), T> {
+        // the old value is dropped outside of the critical section
+        self.swap(value).map(drop)
+    }
+
+    /// Replaces the value stored inside the `AtomicSharedGuard` this
+    /// `AtomicSharedRefGuard` references, returning the old value, or
+    /// `value` in `Err` if there is no such `AtomicSharedGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicSharedGuard::with`] on the current thread.
+    #[inline]
+    pub fn swap(&self, value: T) -> Result<T, T> {
+        critical_section::with(|cs| match self.guard.value_guard(cs) {
+            Some(guard) => Ok(guard.replace(value)),
+            None => Err(value),
+        })
+    }
+}
+
+impl<T: Copy> AtomicSharedRefGuard<T> {
+    /// Gets a copy of the value stored inside the `AtomicSharedGuard` this
+    /// `AtomicSharedRefGuard` references.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.guard.get()
+    }
+}
+
+impl<T: Copy + PartialEq> AtomicSharedRefGuard<T> {
+    /// Replaces the value stored inside the `AtomicSharedGuard` this
+    /// `AtomicSharedRefGuard` references with `new` if it is equal to
+    /// `current`, as with [`AtomicSharedGuard::compare_exchange()`].
+    ///
+    /// Returns `None` if there is no such `AtomicSharedGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`AtomicSharedGuard::with`] on the current thread.
+    #[inline]
+    pub fn compare_exchange(&self, current: T, new: T) -> Option<Result<T, T>> {
+        critical_section::with(|cs| {
+            self.guard
+                .value_guard(cs)
+                .map(|guard| compare_exchange(guard, current, new))
+        })
+    }
+}
+
+impl<T> Default for AtomicSharedRefGuard<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Must be called inside of a critical section to be atomic.
+#[inline]
+fn compare_exchange<T: Copy + PartialEq>(
+    guard: &AtomicValueGuard<T>,
+    current: T,
+    new: T,
+) -> Result<T, T> {
+    let value = guard.get();
+    if value == current {
+        guard.set(new);
+        Ok(value)
+    } else {
+        Err(value)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use core::pin;
+
+    use super::*;
+
+    #[test]
+    fn write_back() {
+        let weak = pin::pin!(AtomicSharedRefGuard::new());
+        {
+            let strong = pin::pin!(AtomicSharedGuard::new(0));
+            weak.as_ref().register(strong.as_ref());
+
+            assert_eq!(weak.as_ref().set(1), Ok(()));
+            assert_eq!(strong.get(), 1);
+            assert_eq!(weak.compare_exchange(1, 2), Some(Ok(1)));
+            assert_eq!(strong.compare_exchange(1, 3), Err(2));
+            assert_eq!(strong.swap(4), 2);
+            assert_eq!(weak.swap(5), Ok(4));
+        }
+
+        assert!(!weak.is_registered());
+        assert_eq!(weak.as_ref().set(6), Err(6));
+        assert_eq!(weak.compare_exchange(5, 6), None);
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    extern crate alloc;
+
+    use alloc::boxed::Box;
+    use loom::thread;
+
+    use super::*;
+
+    #[test]
+    fn compare_exchange_from_both_sides() {
+        loom::model(|| {
+            let strong = Box::pin(AtomicSharedGuard::new(0));
+            let weak = Box::pin(AtomicSharedRefGuard::new());
+            weak.as_ref().register(strong.as_ref());
+
+            let handle = thread::spawn(move || {
+                weak.compare_exchange(0, 1).unwrap().is_ok()
+            });
+            let strong_won = strong.compare_exchange(0, 2).is_ok();
+            let weak_won = handle.join().unwrap();
+
+            // exactly one side observes the initial value
+            assert!(strong_won != weak_won);
+            assert_eq!(strong.get(), if strong_won { 2 } else { 1 });
+        });
+    }
+}
@@ -1,4 +1,13 @@
-use core::{cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull};
+use core::{
+    cell::{Cell, RefCell},
+    marker::PhantomPinned,
+    pin::Pin,
+    ptr::NonNull,
+};
+
+use futures_core::{WakeGuard, WakePtr};
+
+use crate::leak_check::Canary;
 
 /// Strong guard for granting read access to a single interior mutable value to
 /// [`RefGuard`](RefGuard).
@@ -25,10 +34,17 @@ pub struct ValueGuard<T> {
     /// This needs to be a cell so that the original immutable alias
     /// to `Self` (given to `RefGuard`) can continue to be referenced after
     /// invalidated by the creation of a mutable alias for `Self::set`.
-    data: Cell<T>,
+    ///
+    /// It is a `RefCell` rather than a `Cell` so that non-`Copy` values can
+    /// be borrowed by [`RefGuard::with`], which causes any attempt to mutate
+    /// the value from inside the closure to panic.
+    data: RefCell<T>,
     /// A pointer to a `RefGuard` with read access to `data` to invalidate that
     /// `RefGuard` when `Self` is dropped.
     ref_guard: Cell<Option<NonNull<RefGuard<T>>>>,
+    canary: Canary,
+    /// The canary of `ref_guard`, checked before dereferencing it.
+    ref_canary: Cell<Canary>,
     _marker: PhantomPinned,
 }
 
@@ -37,16 +53,46 @@ impl<T> ValueGuard<T> {
     #[inline]
     pub fn new(data: T) -> Self {
         Self {
-            data: Cell::new(data),
+            data: RefCell::new(data),
             ref_guard: Cell::new(None),
+            canary: Canary::new(),
+            ref_canary: Cell::new(Canary::new()),
             _marker: PhantomPinned,
         }
     }
 
     /// Sets the internal value stored by `Self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`RefGuard::with`].
     #[inline]
     pub fn set(&self, value: T) {
-        self.data.set(value);
+        self.replace(value);
+    }
+
+    /// Replaces the internal value stored by `Self`, returning the old value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`RefGuard::with`].
+    #[inline]
+    pub fn replace(&self, value: T) -> T {
+        self.data.replace(value)
+    }
+
+    /// Calls `f` with a reference to the internal value stored by `Self`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.data.borrow())
+    }
+
+    /// Returns `true` if a `RefGuard` is currently registered to `self`.
+    #[inline]
+    pub fn has_ref(&self) -> bool {
+        self.ref_guard.get().is_some()
     }
 
     #[inline]
@@ -57,22 +103,58 @@ impl<T> ValueGuard<T> {
     #[inline]
     fn replace_ref_guard(&self, ref_guard: Option<NonNull<RefGuard<T>>>) {
         if let Some(guard) = self.ref_guard.replace(ref_guard) {
-            unsafe { (*guard.as_ptr()).invalidate_value_guard() };
+            let guard = unsafe { &*guard.as_ptr() };
+            guard.canary.check(self.ref_canary.get(), "RefGuard");
+            guard.invalidate_value_guard();
         }
     }
 }
 
+impl<T: Default> ValueGuard<T> {
+    /// Takes the internal value stored by `Self`, leaving `T::default()` in
+    /// its place.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`RefGuard::with`].
+    #[inline]
+    pub fn take(&self) -> T {
+        self.replace(T::default())
+    }
+
+    /// Updates the internal value stored by `Self` using `f`.
+    ///
+    /// The value is taken out of `Self` for the duration of `f`, so reading
+    /// it from inside `f` will observe `T::default()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`RefGuard::with`].
+    #[inline]
+    pub fn update(&self, f: impl FnOnce(T) -> T) {
+        let value = f(self.take());
+        self.set(value);
+    }
+}
+
 impl<T: Copy> ValueGuard<T> {
     /// Gets a copy of the value stored inside this `ValueGuard`.
     #[inline]
     pub fn get(&self) -> T {
-        self.data.get()
+        *self.data.borrow()
     }
 }
 
 impl<T> Drop for ValueGuard<T> {
     #[inline]
     fn drop(&mut self) {
+        if self.data.try_borrow_mut().is_err() {
+            // `Self` is being dropped from inside of `RefGuard::with`, which
+            // would free the value while it is still borrowed
+            crate::abort("ValueGuard dropped while its value was borrowed");
+        }
         self.replace_ref_guard(None);
     }
 }
@@ -95,6 +177,9 @@ impl<T> Drop for ValueGuard<T> {
 /// is never freed.
 pub struct RefGuard<T> {
     value_guard: Cell<Option<NonNull<ValueGuard<T>>>>,
+    canary: Canary,
+    /// The canary of `value_guard`, checked before dereferencing it.
+    value_canary: Cell<Canary>,
     _marker: PhantomPinned,
 }
 
@@ -104,6 +189,8 @@ impl<T> RefGuard<T> {
     pub fn new() -> Self {
         Self {
             value_guard: Cell::new(None),
+            canary: Canary::new(),
+            value_canary: Cell::new(Canary::new()),
             _marker: PhantomPinned,
         }
     }
@@ -116,7 +203,9 @@ impl<T> RefGuard<T> {
     #[inline]
     fn replace_value_guard(&self, value_guard: Option<NonNull<ValueGuard<T>>>) {
         if let Some(guard) = self.value_guard.replace(value_guard) {
-            unsafe { (*guard.as_ptr()).invalidate_ref_guard() }
+            let guard = unsafe { &*guard.as_ptr() };
+            guard.canary.check(self.value_canary.get(), "ValueGuard");
+            guard.invalidate_ref_guard();
         }
     }
 
@@ -135,6 +224,68 @@ impl<T> RefGuard<T> {
     ) {
         value_guard.replace_ref_guard(Some(self.get_ref().into()));
         self.replace_value_guard(Some(value_guard.get_ref().into()));
+        value_guard.ref_canary.set(self.canary);
+        self.value_canary.set(value_guard.canary);
+    }
+
+    /// Unbinds `self` from the `ValueGuard` it references, if any.
+    ///
+    /// Both guards invalidate their references to each other, as if the
+    /// `ValueGuard` had been dropped.
+    #[inline]
+    pub fn unregister(&self) {
+        self.replace_value_guard(None);
+    }
+
+    /// Returns `true` if `self` currently references a `ValueGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.value_guard.get().is_some()
+    }
+}
+
+impl<T> RefGuard<T> {
+    /// Calls `f` with a reference to the value stored inside the `ValueGuard`
+    /// this `RefGuard` references, returning `None` if there is no such
+    /// `ValueGuard`.
+    ///
+    /// The reference can't escape `f`, so it can never outlive the
+    /// `ValueGuard`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.value_guard().map(|guard| guard.with(f))
+    }
+
+    /// Returns the `ValueGuard` `self` references, if any.
+    ///
+    /// The reference must not be held across anything that could drop the
+    /// `ValueGuard`.
+    #[inline]
+    pub(crate) fn value_guard(&self) -> Option<&ValueGuard<T>> {
+        let guard = unsafe { &*self.value_guard.get()?.as_ptr() };
+        if guard.canary != self.value_canary.get() {
+            // forget the dangling pointer so dropping `self` while unwinding
+            // doesn't dereference it again
+            self.value_guard.set(None);
+            guard.canary.check(self.value_canary.get(), "ValueGuard");
+        }
+        Some(guard)
+    }
+
+    /// Creates a read only handle to the part of the value selected by
+    /// `project`, such as one of its fields.
+    ///
+    /// The handle reads through `self`, so it shares its validity and is
+    /// invalidated along with `self` when the `ValueGuard` is dropped.
+    #[inline]
+    pub fn map<U: ?Sized>(
+        &self,
+        project: fn(&T) -> &U,
+    ) -> MappedRefGuard<'_, T, U> {
+        MappedRefGuard {
+            ref_guard: self,
+            project,
+        }
     }
 }
 
@@ -143,9 +294,48 @@ impl<T: Copy> RefGuard<T> {
     /// references.
     #[inline]
     pub fn get(&self) -> Option<T> {
-        self.value_guard
-            .get()
-            .map(|guard| unsafe { (*guard.as_ptr()).get() })
+        self.with(|value| *value)
+    }
+}
+
+/// Read only handle to part of the value referenced by a [`RefGuard`],
+/// created by [`RefGuard::map()`].
+pub struct MappedRefGuard<'a, T, U: ?Sized> {
+    ref_guard: &'a RefGuard<T>,
+    project: fn(&T) -> &U,
+}
+
+impl<T, U: ?Sized> Clone for MappedRefGuard<'_, T, U> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, U: ?Sized> Copy for MappedRefGuard<'_, T, U> {}
+
+impl<'a, T, U: ?Sized> MappedRefGuard<'a, T, U> {
+    /// Calls `f` with a reference to the projected value, returning `None`
+    /// if the parent `RefGuard` does not reference a `ValueGuard`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&U) -> R) -> Option<R> {
+        let project = self.project;
+        self.ref_guard.with(|value| f(project(value)))
+    }
+
+    /// Returns `true` if the parent `RefGuard` currently references a
+    /// `ValueGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.ref_guard.is_registered()
+    }
+}
+
+impl<T, U: Copy> MappedRefGuard<'_, T, U> {
+    /// Gets a copy of the projected value.
+    #[inline]
+    pub fn get(&self) -> Option<U> {
+        self.with(|value| *value)
     }
 }
 
@@ -163,6 +353,40 @@ impl<T: Copy> Default for RefGuard<T> {
     }
 }
 
+impl WakeGuard for ValueGuard<WakePtr> {
+    type Parent = RefGuard<WakePtr>;
+    type Ptr = WakePtr;
+
+    #[inline]
+    fn new(wake: WakePtr) -> Self {
+        Self::new(wake)
+    }
+
+    #[inline]
+    fn set(&self, wake: WakePtr) {
+        self.set(wake);
+    }
+
+    #[inline]
+    fn wake(&self) {
+        // the task pointed to by a waker outlives the waker
+        unsafe { self.get().wake() }
+    }
+
+    #[inline]
+    fn register_parent<'a>(self: Pin<&'a Self>, parent: Pin<&'a Self::Parent>) {
+        parent.register(self);
+    }
+
+    #[inline]
+    fn wake_parent(parent: &Self::Parent) {
+        // `parent` only reads the pointer while its waker is alive
+        if let Some(wake) = parent.get() {
+            unsafe { wake.wake() }
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use core::{mem, pin};
@@ -221,6 +445,125 @@ mod test {
         assert_eq!(weak2.get(), None);
     }
 
+    #[test]
+    fn unregister() {
+        let weak = pin::pin!(RefGuard::new());
+        let strong = pin::pin!(ValueGuard::new(2));
+        assert!(!weak.is_registered());
+        assert!(!strong.has_ref());
+
+        weak.as_ref().register(strong.as_ref());
+        assert!(weak.is_registered());
+        assert!(strong.has_ref());
+
+        weak.unregister();
+        assert!(!weak.is_registered());
+        assert!(!strong.has_ref());
+        assert_eq!(weak.get(), None);
+
+        // unregistering twice is a no-op
+        weak.unregister();
+
+        weak.as_ref().register(strong.as_ref());
+        assert_eq!(weak.get(), Some(2));
+    }
+
+    #[test]
+    fn non_copy() {
+        #[derive(Debug, Default, PartialEq)]
+        struct Snapshot {
+            position: [i32; 3],
+            label: alloc::string::String,
+        }
+
+        let weak = pin::pin!(RefGuard::new());
+        {
+            let strong = pin::pin!(ValueGuard::new(Snapshot {
+                position: [1, 2, 3],
+                label: "start".into(),
+            }));
+            weak.as_ref().register(strong.as_ref());
+
+            assert_eq!(weak.with(|s| s.position[1]), Some(2));
+            assert_eq!(strong.with(|s| s.label.len()), 5);
+
+            let old = strong.replace(Snapshot::default());
+            assert_eq!(old.label, "start");
+            assert_eq!(weak.with(|s| s.label.is_empty()), Some(true));
+
+            strong.update(|mut s| {
+                s.position[0] = 4;
+                s
+            });
+            assert_eq!(weak.with(|s| s.position), Some([4, 0, 0]));
+
+            assert_eq!(strong.take().position, [4, 0, 0]);
+            assert_eq!(weak.with(|s| s.position), Some([0, 0, 0]));
+        }
+
+        assert_eq!(weak.with(|s| s.position), None);
+    }
+
+    #[test]
+    fn map() {
+        struct Pair {
+            left: i32,
+            right: [u8; 2],
+        }
+
+        let weak = pin::pin!(RefGuard::new());
+        let left = weak.map(|pair: &Pair| &pair.left);
+        let right = weak.map(|pair: &Pair| &pair.right[..]);
+        assert_eq!(left.get(), None);
+        {
+            let strong = pin::pin!(ValueGuard::new(Pair {
+                left: 1,
+                right: [2, 3],
+            }));
+            weak.as_ref().register(strong.as_ref());
+            assert!(left.is_registered());
+            assert_eq!(left.get(), Some(1));
+            assert_eq!(right.with(|right| right.len()), Some(2));
+
+            strong.as_ref().set(Pair {
+                left: 4,
+                right: [5, 6],
+            });
+            assert_eq!(left.get(), Some(4));
+            assert_eq!(right.with(|right| right[1]), Some(6));
+        }
+
+        // the projections share the validity of weak
+        assert!(!left.is_registered());
+        assert_eq!(left.get(), None);
+        assert_eq!(right.with(|right| right[0]), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_while_borrowed() {
+        let weak = pin::pin!(RefGuard::new());
+        let strong = pin::pin!(ValueGuard::new(1));
+        weak.as_ref().register(strong.as_ref());
+
+        weak.with(|_| strong.as_ref().set(2));
+    }
+
+    #[test]
+    #[cfg(all(feature = "leak-check", debug_assertions))]
+    #[should_panic(expected = "ValueGuard was leaked")]
+    fn leak_check() {
+        let weak = pin::pin!(RefGuard::new());
+        let mut strong = mem::MaybeUninit::new(ValueGuard::new(1));
+        weak.as_ref()
+            .register(unsafe { Pin::new_unchecked(strong.assume_init_ref()) });
+
+        // overwrite strong without dropping it, as happens to a guard leaked
+        // by a stack frame that has since returned
+        strong.write(ValueGuard::new(2));
+        weak.get();
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn safe_leak() {
@@ -234,4 +577,28 @@ mod test {
 
         assert_eq!(weak.get(), Some(10));
     }
+
+    #[test]
+    fn waker_slot() {
+        use futures_core::{
+            WakerRegistration, WakerSlot,
+            test_util::{Counter, waker},
+        };
+
+        let counter = Counter::new();
+        let slot = pin::pin!(WakerSlot::<ValueGuard<WakePtr>>::new());
+        {
+            let waker =
+                pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+            WakerRegistration::new(waker.as_ref()).register(slot.as_ref());
+            assert!(waker.has_ref());
+
+            slot.wake();
+            assert_eq!(counter.count(), 1);
+        }
+
+        // the waker unbound itself when dropped
+        slot.wake();
+        assert_eq!(counter.count(), 1);
+    }
 }
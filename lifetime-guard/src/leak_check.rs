@@ -0,0 +1,60 @@
+//! Canaries for detecting guards that were leaked to the stack, enabled by
+//! the `leak-check` feature in debug builds.
+//!
+//! Every guard carries a unique [`Canary`], and remembers the canary of the
+//! guard it references when registering. If the referenced guard is leaked
+//! and its memory is overwritten, its canary no longer matches, which is
+//! checked before dereferencing it.
+//!
+//! Without `leak-check`, or with `debug_assertions` disabled, [`Canary`] is
+//! zero sized and all checks compile away.
+
+/// Identifies a single guard instance.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Canary(
+    #[cfg(all(feature = "leak-check", debug_assertions))] usize,
+);
+
+#[cfg(all(feature = "leak-check", debug_assertions))]
+impl Canary {
+    /// Arbitrary offset so zeroed memory is never mistaken for a guard.
+    const MAGIC: usize = usize::MAX / 3;
+
+    /// Returns a new canary, distinct from recently created ones.
+    #[inline]
+    pub(crate) fn new() -> Self {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        // only loads and stores are used so targets without compare and swap
+        // are supported, racing guards may rarely share a canary
+        static GENERATION: AtomicUsize = AtomicUsize::new(0);
+        let generation = GENERATION.load(Ordering::Relaxed).wrapping_add(1);
+        GENERATION.store(generation, Ordering::Relaxed);
+        Self(Self::MAGIC.wrapping_add(generation))
+    }
+
+    /// Panics if `self`, read from a referenced guard, is not `expected`.
+    #[inline]
+    #[track_caller]
+    pub(crate) fn check(self, expected: Self, guard: &str) {
+        if self != expected {
+            panic!(
+                "{guard} was leaked without being dropped, and its memory was \
+                 overwritten while still referenced (expected canary {:#x}, \
+                 found {:#x})",
+                expected.0, self.0
+            );
+        }
+    }
+}
+
+#[cfg(not(all(feature = "leak-check", debug_assertions)))]
+impl Canary {
+    #[inline(always)]
+    pub(crate) fn new() -> Self {
+        Self()
+    }
+
+    #[inline(always)]
+    pub(crate) fn check(self, _expected: Self, _guard: &str) {}
+}
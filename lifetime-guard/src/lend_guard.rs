@@ -0,0 +1,173 @@
+use core::{
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+    ptr::NonNull,
+};
+
+use crate::guard::{RefGuard, ValueGuard};
+
+/// Strong guard for lending a borrowed `&'a T` to a [`LendRefGuard`], which
+/// may outlive `Self`.
+///
+/// `Self` revokes the access of its `LendRefGuard` when dropped, and neither
+/// can outlive `'a`, so the reference is never used after `'a` ends. This
+/// allows handing non-`'static` data, such as a buffer on the stack, to a
+/// reactor that outlives the lender.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+pub struct LendGuard<'a, T: ?Sized> {
+    guard: ValueGuard<NonNull<T>>,
+    _lifetime: PhantomData<&'a T>,
+    _marker: PhantomPinned,
+}
+
+impl<'a, T: ?Sized> LendGuard<'a, T> {
+    /// Creates a new `LendGuard` lending `value`.
+    #[inline]
+    pub fn new(value: &'a T) -> Self {
+        Self {
+            guard: ValueGuard::new(value.into()),
+            _lifetime: PhantomData,
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Returns `true` if a `LendRefGuard` is currently registered to `self`.
+    #[inline]
+    pub fn has_ref(&self) -> bool {
+        self.guard.has_ref()
+    }
+}
+
+/// Weak guard for acquiring access to the reference lent by a `LendGuard`.
+///
+/// `Self` can only be registered to a `LendGuard<'a, T>`, so it can't outlive
+/// the reference it was lent, even if that `LendGuard` is leaked.
+///
+/// ```rust,compile_fail
+/// use lifetime_guard::lend_guard::{LendGuard, LendRefGuard};
+///
+/// let reactor = core::pin::pin!(LendRefGuard::<[u8]>::new());
+/// {
+///     let buffer = vec![1, 2, 3];
+///     let lender = Box::pin(LendGuard::new(&buffer[..]));
+///     reactor.as_ref().register(lender.as_ref());
+///     core::mem::forget(lender);
+/// }
+/// reactor.with(|buffer| buffer.len());
+/// ```
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+pub struct LendRefGuard<'a, T: ?Sized> {
+    guard: RefGuard<NonNull<T>>,
+    // invariant, so `register` can't shorten `'a` to fit a shorter lived
+    // `LendGuard`
+    _lifetime: PhantomData<fn(&'a T) -> &'a T>,
+    _marker: PhantomPinned,
+}
+
+impl<'a, T: ?Sized> LendRefGuard<'a, T> {
+    /// Creates a new `LendRefGuard` with no reference to a `LendGuard`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            guard: RefGuard::new(),
+            _lifetime: PhantomData,
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Binds a pinned `lend_guard` to `self`.
+    ///
+    /// This behaves like [`RefGuard::register()`].
+    #[inline]
+    pub fn register<'b>(
+        self: Pin<&'b LendRefGuard<'a, T>>,
+        lend_guard: Pin<&'b LendGuard<'a, T>>,
+    ) {
+        let guard = unsafe { self.map_unchecked(|this| &this.guard) };
+        guard.register(unsafe { lend_guard.map_unchecked(|this| &this.guard) });
+    }
+
+    /// Unbinds `self` from the `LendGuard` it references, if any.
+    #[inline]
+    pub fn unregister(&self) {
+        self.guard.unregister();
+    }
+
+    /// Returns `true` if `self` currently references a `LendGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.guard.is_registered()
+    }
+
+    /// Calls `f` with the reference lent by the `LendGuard` this
+    /// `LendRefGuard` references, returning `None` if there is no such
+    /// `LendGuard`.
+    ///
+    /// The reference can't escape `f`, so it can never outlive the
+    /// `LendGuard`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        // the `LendGuard` can't be dropped while its value is borrowed by
+        // `RefGuard::with`, so the pointer stays valid for the duration of `f`
+        self.guard.with(|value| f(unsafe { value.as_ref() }))
+    }
+}
+
+impl<T: ?Sized> Default for LendRefGuard<'_, T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin;
+
+    use super::*;
+
+    #[test]
+    fn lend_stack_buffer() {
+        let buffer = [1, 2, 3];
+        let reactor = pin::pin!(LendRefGuard::<[u8]>::new());
+        {
+            let lender = pin::pin!(LendGuard::new(&buffer[..]));
+            reactor.as_ref().register(lender.as_ref());
+
+            assert!(lender.has_ref());
+            assert_eq!(reactor.with(|buffer| buffer.len()), Some(3));
+            assert_eq!(reactor.with(|buffer| buffer[2]), Some(3));
+        }
+
+        // the lender is gone, and so is the reactor's access to the buffer
+        assert!(!reactor.is_registered());
+        assert_eq!(reactor.with(|buffer| buffer.len()), None);
+    }
+
+    #[test]
+    fn unregister() {
+        let value = 5;
+        let lender = pin::pin!(LendGuard::new(&value));
+        let reactor = pin::pin!(LendRefGuard::new());
+        reactor.as_ref().register(lender.as_ref());
+        assert_eq!(reactor.with(|value| *value), Some(5));
+
+        reactor.unregister();
+        assert!(!lender.has_ref());
+        assert_eq!(reactor.with(|value| *value), None);
+    }
+}
@@ -1,8 +1,47 @@
 #![doc = include_str!("../README.md")]
 #![no_std]
 
+#[cfg(loom)]
+extern crate std;
+
 // pub mod base;
+pub mod array_guard;
 pub mod guard;
+pub mod lend_guard;
+pub mod multi_guard;
+pub mod notify;
+pub mod scope;
+pub mod shared_guard;
+
+pub use scope::scope;
+
+mod leak_check;
 
 #[cfg(feature = "atomics")]
 pub mod atomic_guard;
+
+#[cfg(feature = "atomics")]
+pub mod atomic_shared_guard;
+
+#[cfg(feature = "spin")]
+pub mod spin_guard;
+
+#[cfg(any(feature = "atomics", feature = "spin"))]
+mod sync;
+
+/// Aborts the process with `message`.
+///
+/// `core` has no way to abort, so this panics while already panicking.
+#[cold]
+pub(crate) fn abort(message: &str) -> ! {
+    struct Abort;
+
+    impl Drop for Abort {
+        fn drop(&mut self) {
+            panic!("aborting");
+        }
+    }
+
+    let _abort = Abort;
+    panic!("{message}");
+}
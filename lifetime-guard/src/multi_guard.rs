@@ -0,0 +1,270 @@
+use core::{cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull};
+
+/// Strong guard for granting read access to a single interior mutable value to
+/// any number of [`MultiRefGuard`](MultiRefGuard)s.
+///
+/// Unlike [`ValueGuard`](crate::guard::ValueGuard), registering a new
+/// `MultiRefGuard` does not invalidate the previously registered ones.
+/// Registered `MultiRefGuard`s form an intrusive doubly linked list starting
+/// at `Self`, so no heap allocation is required.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct MultiValueGuard<T> {
+    /// Contains the value being immutably accessed by `MultiRefGuard`s and
+    /// mutably accessed by `Self`
+    data: Cell<T>,
+    /// The first `MultiRefGuard` in the list of guards with read access to
+    /// `data`, used to invalidate all of them when `Self` is dropped.
+    head: Cell<Option<NonNull<MultiRefGuard<T>>>>,
+    _marker: PhantomPinned,
+}
+
+impl<T> MultiValueGuard<T> {
+    /// Creates a new `MultiValueGuard` containing `data`.
+    #[inline]
+    pub fn new(data: T) -> Self {
+        Self {
+            data: Cell::new(data),
+            head: Cell::new(None),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Sets the internal value stored by `Self`.
+    #[inline]
+    pub fn set(&self, value: T) {
+        self.data.set(value);
+    }
+
+    /// Pushes `ref_guard` to the front of the list of registered guards.
+    ///
+    /// `ref_guard` must not currently be linked into any list.
+    #[inline]
+    fn push_ref_guard(&self, ref_guard: NonNull<MultiRefGuard<T>>) {
+        let next = self.head.replace(Some(ref_guard));
+        if let Some(next) = next {
+            unsafe { (*next.as_ptr()).prev.set(Some(ref_guard)) };
+        }
+        unsafe { (*ref_guard.as_ptr()).next.set(next) };
+    }
+}
+
+impl<T: Copy> MultiValueGuard<T> {
+    /// Gets a copy of the value stored inside this `MultiValueGuard`.
+    #[inline]
+    pub fn get(&self) -> T {
+        self.data.get()
+    }
+}
+
+impl<T> Drop for MultiValueGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        let mut current = self.head.take();
+        while let Some(guard) = current {
+            let guard = unsafe { &*guard.as_ptr() };
+            current = guard.next.take();
+            guard.prev.set(None);
+            guard.value_guard.set(None);
+        }
+    }
+}
+
+/// Weak guard for acquiring read only access to a `MultiValueGuard`'s value.
+///
+/// Provides [`MultiRefGuard::register()`](Self::register) to add `Self` to
+/// the list of guards referencing a `MultiValueGuard`.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct MultiRefGuard<T> {
+    value_guard: Cell<Option<NonNull<MultiValueGuard<T>>>>,
+    prev: Cell<Option<NonNull<MultiRefGuard<T>>>>,
+    next: Cell<Option<NonNull<MultiRefGuard<T>>>>,
+    _marker: PhantomPinned,
+}
+
+impl<T> MultiRefGuard<T> {
+    /// Creates a new `MultiRefGuard` with no reference to a
+    /// `MultiValueGuard`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            value_guard: Cell::new(None),
+            prev: Cell::new(None),
+            next: Cell::new(None),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Removes `self` from the list of the `MultiValueGuard` it references,
+    /// if any.
+    #[inline]
+    fn unlink(&self) {
+        let Some(value_guard) = self.value_guard.take() else {
+            return;
+        };
+        let prev = self.prev.take();
+        let next = self.next.take();
+
+        match prev {
+            Some(prev) => unsafe { (*prev.as_ptr()).next.set(next) },
+            None => unsafe { (*value_guard.as_ptr()).head.set(next) },
+        }
+        if let Some(next) = next {
+            unsafe { (*next.as_ptr()).prev.set(prev) };
+        }
+    }
+
+    /// Binds a pinned `value_guard` to `self`.
+    ///
+    /// This adds `self` to the list of guards referencing `value_guard`,
+    /// without affecting any other guards already in that list. If `self`
+    /// was previously bound to another `MultiValueGuard`, it is removed from
+    /// that guard's list first.
+    #[inline]
+    pub fn register<'a>(
+        self: Pin<&'a MultiRefGuard<T>>,
+        value_guard: Pin<&'a MultiValueGuard<T>>,
+    ) {
+        self.unlink();
+        value_guard.push_ref_guard(self.get_ref().into());
+        self.value_guard.set(Some(value_guard.get_ref().into()));
+    }
+}
+
+impl<T: Copy> MultiRefGuard<T> {
+    /// Gets a copy of the value stored inside the `MultiValueGuard` this
+    /// `MultiRefGuard` references.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.value_guard
+            .get()
+            .map(|guard| unsafe { (*guard.as_ptr()).get() })
+    }
+}
+
+impl<T> Drop for MultiRefGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.unlink();
+    }
+}
+
+impl<T> Default for MultiRefGuard<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::{mem, pin};
+
+    extern crate alloc;
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let weak1 = pin::pin!(MultiRefGuard::new());
+        let weak2 = pin::pin!(MultiRefGuard::new());
+        {
+            let strong = pin::pin!(MultiValueGuard::new(2));
+            weak1.as_ref().register(strong.as_ref());
+            weak2.as_ref().register(strong.as_ref());
+
+            assert_eq!(strong.get(), 2);
+            assert_eq!(weak1.get(), Some(2));
+            assert_eq!(weak2.get(), Some(2));
+
+            strong.as_ref().set(3);
+            assert_eq!(weak1.get(), Some(3));
+            assert_eq!(weak2.get(), Some(3));
+        }
+
+        assert_eq!(weak1.get(), None);
+        assert_eq!(weak2.get(), None);
+    }
+
+    #[test]
+    fn drop_ref_guards() {
+        let strong = pin::pin!(MultiValueGuard::new(1));
+        let weak1 = pin::pin!(MultiRefGuard::new());
+        weak1.as_ref().register(strong.as_ref());
+        {
+            let weak2 = pin::pin!(MultiRefGuard::new());
+            let weak3 = pin::pin!(MultiRefGuard::new());
+            weak2.as_ref().register(strong.as_ref());
+            weak3.as_ref().register(strong.as_ref());
+            // list is now weak3 -> weak2 -> weak1, dropped from the front
+            // (weak3) and the middle (weak2)
+        }
+        assert_eq!(weak1.get(), Some(1));
+        assert_eq!(weak1.prev.get(), None);
+        assert_eq!(weak1.next.get(), None);
+        assert_eq!(strong.head.get(), Some(weak1.as_ref().get_ref().into()));
+
+        {
+            let weak2 = pin::pin!(MultiRefGuard::new());
+            weak2.as_ref().register(strong.as_ref());
+            // drop the back of the list (weak1) by registering it elsewhere
+            let other = pin::pin!(MultiValueGuard::new(5));
+            weak1.as_ref().register(other.as_ref());
+            assert_eq!(weak1.get(), Some(5));
+            assert_eq!(weak2.get(), Some(1));
+            assert_eq!(weak2.next.get(), None);
+        }
+        assert_eq!(weak1.get(), None);
+        assert_eq!(strong.head.get(), None);
+    }
+
+    #[test]
+    fn reregister_same_guard() {
+        let strong = pin::pin!(MultiValueGuard::new(1));
+        let weak = pin::pin!(MultiRefGuard::new());
+        weak.as_ref().register(strong.as_ref());
+        weak.as_ref().register(strong.as_ref());
+
+        assert_eq!(weak.get(), Some(1));
+        assert_eq!(weak.next.get(), None);
+        assert_eq!(strong.head.get(), Some(weak.as_ref().get_ref().into()));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn safe_leak() {
+        let strong = alloc::boxed::Box::pin(MultiValueGuard::new(10));
+        let weak1 = pin::pin!(MultiRefGuard::new());
+        let weak2 = pin::pin!(MultiRefGuard::new());
+        weak1.as_ref().register(strong.as_ref());
+        weak2.as_ref().register(strong.as_ref());
+
+        // strong is now a MultiValueGuard on the heap that will never be
+        // freed, this is sound because it will never be overwritten
+        mem::forget(strong);
+
+        assert_eq!(weak1.get(), Some(10));
+        assert_eq!(weak2.get(), Some(10));
+    }
+}
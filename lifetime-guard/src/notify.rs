@@ -0,0 +1,521 @@
+use core::{
+    cell::Cell, marker::PhantomPinned, pin::Pin, ptr::NonNull, task::Poll,
+};
+
+use futures_core::{
+    FusedStream, Future, Stream, WakeGuard, WakePtr, WakerRegistration,
+    WakerSlot,
+};
+
+use crate::guard::{RefGuard, ValueGuard};
+
+/// [`ValueGuard`] that wakes a waker registered by its
+/// [`NotifyingRefGuard`] whenever its value changes.
+///
+/// The waker is itself a guard, `ValueGuard<WakePtr>` unless another
+/// [`WakeGuard`] is chosen, which `Self` references through a
+/// [`WakerSlot`], so the pair behaves like a "watch" cell without requiring
+/// heap allocation.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct NotifyingValueGuard<T, Waker: WakeGuard = ValueGuard<WakePtr>> {
+    guard: ValueGuard<T>,
+    /// References the waker registered by the `NotifyingRefGuard`, if any.
+    notifier: WakerSlot<Waker>,
+    /// Incremented on every notification, so [`Changed`] can tell whether
+    /// the value changed since it was created.
+    version: Cell<usize>,
+    _marker: PhantomPinned,
+}
+
+impl<T, Waker: WakeGuard> NotifyingValueGuard<T, Waker> {
+    /// Creates a new `NotifyingValueGuard` containing `data`.
+    #[inline]
+    pub fn new(data: T) -> Self {
+        Self {
+            guard: ValueGuard::new(data),
+            notifier: WakerSlot::new(),
+            version: Cell::new(0),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Replaces the internal value stored by `Self`, returning the old value.
+    ///
+    /// This always notifies the registered waker, since `T` can't be
+    /// compared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`NotifyingRefGuard::with`].
+    #[inline]
+    pub fn replace(&self, value: T) -> T {
+        let old = self.guard.replace(value);
+        self.notify();
+        old
+    }
+
+    /// Calls `f` with a reference to the internal value stored by `Self`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.guard.with(f)
+    }
+
+    /// Wakes the waker registered by the `NotifyingRefGuard` of `self`, if
+    /// any.
+    #[inline]
+    pub fn notify(&self) {
+        self.version.set(self.version.get().wrapping_add(1));
+        self.notifier.wake();
+    }
+}
+
+impl<T: PartialEq, Waker: WakeGuard> NotifyingValueGuard<T, Waker> {
+    /// Sets the internal value stored by `Self`, notifying the registered
+    /// waker if it is not equal to the previous value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`NotifyingRefGuard::with`].
+    #[inline]
+    pub fn set(&self, value: T) {
+        let changed = self.guard.with(|old| *old != value);
+        self.guard.set(value);
+        if changed {
+            self.notify();
+        }
+    }
+}
+
+impl<T: Copy, Waker: WakeGuard> NotifyingValueGuard<T, Waker> {
+    /// Gets a copy of the value stored inside this `NotifyingValueGuard`.
+    #[inline]
+    pub fn get(&self) -> T {
+        self.guard.get()
+    }
+}
+
+impl<T, Waker: WakeGuard> Drop for NotifyingValueGuard<T, Waker> {
+    #[inline]
+    fn drop(&mut self) {
+        // the value is about to be invalidated, which observers should also
+        // learn about
+        self.notify();
+    }
+}
+
+/// Weak guard for acquiring read only access to a `NotifyingValueGuard`'s
+/// value, and registering a waker to be woken when it changes.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct NotifyingRefGuard<T, Waker: WakeGuard = ValueGuard<WakePtr>> {
+    guard: RefGuard<T>,
+    /// The `NotifyingValueGuard` containing the `ValueGuard` referenced by
+    /// `guard`, only valid while `guard` is registered.
+    value_guard: Cell<Option<NonNull<NotifyingValueGuard<T, Waker>>>>,
+    _marker: PhantomPinned,
+}
+
+impl<T, Waker: WakeGuard> NotifyingRefGuard<T, Waker> {
+    /// Creates a new `NotifyingRefGuard` with no reference to a
+    /// `NotifyingValueGuard`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            guard: RefGuard::new(),
+            value_guard: Cell::new(None),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Binds a pinned `value_guard` to `self`.
+    ///
+    /// This behaves like [`RefGuard::register()`], and does not register a
+    /// waker.
+    #[inline]
+    pub fn register<'a>(
+        self: Pin<&'a NotifyingRefGuard<T, Waker>>,
+        value_guard: Pin<&'a NotifyingValueGuard<T, Waker>>,
+    ) {
+        let guard = unsafe { self.map_unchecked(|this| &this.guard) };
+        guard
+            .register(unsafe { value_guard.map_unchecked(|this| &this.guard) });
+        self.value_guard.set(Some(value_guard.get_ref().into()));
+    }
+
+    /// Registers `waker` to be woken whenever the value of the
+    /// `NotifyingValueGuard` `self` references changes or is dropped,
+    /// replacing any previously registered waker.
+    ///
+    /// Does nothing if `self` does not reference a `NotifyingValueGuard`.
+    #[inline]
+    pub fn register_waker<'a>(
+        self: Pin<&'a NotifyingRefGuard<T, Waker>>,
+        waker: Pin<&'a Waker>,
+    ) {
+        if let Some(value_guard) = self.value_guard() {
+            WakerRegistration::new(waker)
+                .register(unsafe { Pin::new_unchecked(&value_guard.notifier) });
+        }
+    }
+
+    /// Unbinds `self` from the `NotifyingValueGuard` it references, if any.
+    #[inline]
+    pub fn unregister(&self) {
+        self.guard.unregister();
+    }
+
+    /// Returns `true` if `self` currently references a
+    /// `NotifyingValueGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.guard.is_registered()
+    }
+
+    /// Calls `f` with a reference to the value stored inside the
+    /// `NotifyingValueGuard` this `NotifyingRefGuard` references, returning
+    /// `None` if there is no such `NotifyingValueGuard`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.guard.with(f)
+    }
+
+    /// Returns a future that resolves once the value of the
+    /// `NotifyingValueGuard` `self` references changes, or to `None` once
+    /// `self` no longer references a `NotifyingValueGuard`.
+    ///
+    /// Only changes that happen after this method is called are observed.
+    /// Each poll registers the polling waker with
+    /// [`Self::register_waker`], replacing any previously registered waker.
+    #[inline]
+    pub fn changed(self: Pin<&Self>) -> Changed<'_, T, Waker> {
+        Changed {
+            ref_guard: self,
+            version: self.value_guard().map(|guard| guard.version.get()),
+        }
+    }
+
+    /// Returns a stream of the values of the `NotifyingValueGuard` `self`
+    /// references, which yields every time it changes, and ends once `self`
+    /// no longer references a `NotifyingValueGuard`.
+    ///
+    /// Like [`Self::changed`], only changes that happen after this method is
+    /// called are observed, and several changes between polls are yielded
+    /// as one.
+    #[inline]
+    pub fn changes(self: Pin<&Self>) -> Changes<'_, T, Waker> {
+        Changes {
+            changed: self.changed(),
+            terminated: false,
+        }
+    }
+
+    #[inline]
+    fn value_guard(&self) -> Option<&NotifyingValueGuard<T, Waker>> {
+        // `guard` can only be registered through `Self::register`, so while
+        // it is registered `value_guard` points to its `ValueGuard`'s parent
+        self.guard
+            .is_registered()
+            .then(|| self.value_guard.get())
+            .flatten()
+            .map(|guard| unsafe { &*guard.as_ptr() })
+    }
+}
+
+impl<T: Copy, Waker: WakeGuard> NotifyingRefGuard<T, Waker> {
+    /// Gets a copy of the value stored inside the `NotifyingValueGuard` this
+    /// `NotifyingRefGuard` references.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.guard.get()
+    }
+}
+
+impl<T, Waker: WakeGuard> Default for NotifyingRefGuard<T, Waker> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Future returned by [`NotifyingRefGuard::changed`].
+///
+/// Together with a [`NotifyingValueGuard`], this behaves like the receiving
+/// half of a watch channel that requires no heap allocation.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Changed<'a, T, Waker: WakeGuard = ValueGuard<WakePtr>> {
+    ref_guard: Pin<&'a NotifyingRefGuard<T, Waker>>,
+    /// Version of the `NotifyingValueGuard` when `Self` was created.
+    version: Option<usize>,
+}
+
+impl<T: Clone, Waker: WakeGuard> Future<Waker> for Changed<'_, T, Waker> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, waker: Pin<&Waker>) -> Poll<Self::Output> {
+        let Some(value_guard) = self.ref_guard.value_guard() else {
+            return Poll::Ready(None);
+        };
+        if Some(value_guard.version.get()) != self.version {
+            return Poll::Ready(Some(value_guard.with(T::clone)));
+        }
+        self.ref_guard.register_waker(waker);
+        Poll::Pending
+    }
+}
+
+/// Stream returned by [`NotifyingRefGuard::changes`].
+#[must_use = "streams do nothing unless polled"]
+pub struct Changes<'a, T, Waker: WakeGuard = ValueGuard<WakePtr>> {
+    changed: Changed<'a, T, Waker>,
+    terminated: bool,
+}
+
+impl<T: Clone, Waker: WakeGuard> Stream<Waker> for Changes<'_, T, Waker> {
+    type Item = T;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        waker: Pin<&Waker>,
+    ) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.changed).poll(waker);
+        match poll {
+            Poll::Ready(Some(_)) => {
+                // only yield changes after this one
+                self.changed = self.changed.ref_guard.changed();
+            }
+            Poll::Ready(None) => self.terminated = true,
+            Poll::Pending => {}
+        }
+        poll
+    }
+}
+
+impl<T: Clone, Waker: WakeGuard> FusedStream<Waker> for Changes<'_, T, Waker> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin;
+
+    use futures_core::test_util::{Counter, waker};
+
+    use super::*;
+
+    #[test]
+    fn notify_on_change() {
+        let counter = Counter::new();
+        let waker =
+            pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+        let weak = pin::pin!(NotifyingRefGuard::new());
+        {
+            let strong = pin::pin!(NotifyingValueGuard::new(1));
+            weak.as_ref().register(strong.as_ref());
+            weak.as_ref().register_waker(waker.as_ref());
+
+            strong.as_ref().set(1);
+            assert_eq!(counter.count(), 0);
+
+            strong.as_ref().set(2);
+            assert_eq!(counter.count(), 1);
+            assert_eq!(weak.get(), Some(2));
+
+            strong.replace(2);
+            assert_eq!(counter.count(), 2);
+        }
+
+        // dropping the value guard notifies too
+        assert_eq!(counter.count(), 3);
+        assert_eq!(weak.get(), None);
+    }
+
+    #[test]
+    fn waker_dropped_first() {
+        let strong = pin::pin!(NotifyingValueGuard::new(1));
+        let weak = pin::pin!(NotifyingRefGuard::new());
+        weak.as_ref().register(strong.as_ref());
+        {
+            let counter = Counter::new();
+            let waker =
+                pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+            weak.as_ref().register_waker(waker.as_ref());
+            strong.as_ref().set(2);
+            assert_eq!(counter.count(), 1);
+        }
+
+        // the waker invalidated itself, so nothing is woken
+        strong.as_ref().set(3);
+        assert_eq!(weak.get(), Some(3));
+    }
+
+    #[test]
+    fn unregistered() {
+        let counter = Counter::new();
+        let waker =
+            pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+        let weak = pin::pin!(NotifyingRefGuard::<i32>::new());
+
+        // nothing to register the waker to
+        weak.as_ref().register_waker(waker.as_ref());
+        assert!(!waker.has_ref());
+
+        let strong = pin::pin!(NotifyingValueGuard::new(1));
+        weak.as_ref().register(strong.as_ref());
+        weak.unregister();
+        weak.as_ref().register_waker(waker.as_ref());
+        assert!(!waker.has_ref());
+    }
+
+    #[test]
+    fn changed() {
+        let counter = Counter::new();
+        let waker =
+            pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+        let weak = pin::pin!(NotifyingRefGuard::new());
+        let strong = pin::pin!(NotifyingValueGuard::new(1));
+        weak.as_ref().register(strong.as_ref());
+
+        let mut changed = weak.as_ref().changed();
+        let mut changed = Pin::new(&mut changed);
+        assert_eq!(changed.as_mut().poll(waker.as_ref()), Poll::Pending);
+
+        // setting an equal value is not a change
+        strong.as_ref().set(1);
+        assert_eq!(counter.count(), 0);
+        assert_eq!(changed.as_mut().poll(waker.as_ref()), Poll::Pending);
+
+        strong.as_ref().set(2);
+        assert_eq!(counter.count(), 1);
+        assert_eq!(changed.poll(waker.as_ref()), Poll::Ready(Some(2)));
+
+        // a new future only observes later changes
+        let mut changed = weak.as_ref().changed();
+        assert_eq!(Pin::new(&mut changed).poll(waker.as_ref()), Poll::Pending);
+    }
+
+    #[test]
+    fn changed_value_guard_dropped() {
+        let counter = Counter::new();
+        let waker =
+            pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+        let weak = pin::pin!(NotifyingRefGuard::new());
+        let mut changed = {
+            let strong = pin::pin!(NotifyingValueGuard::new(1));
+            weak.as_ref().register(strong.as_ref());
+
+            let mut changed = weak.as_ref().changed();
+            assert_eq!(
+                Pin::new(&mut changed).poll(waker.as_ref()),
+                Poll::Pending
+            );
+            changed
+        };
+
+        assert_eq!(counter.count(), 1);
+        assert_eq!(
+            Pin::new(&mut changed).poll(waker.as_ref()),
+            Poll::Ready(None)
+        );
+
+        // never registered
+        let weak = pin::pin!(NotifyingRefGuard::<i32>::new());
+        let mut changed = weak.as_ref().changed();
+        assert_eq!(
+            Pin::new(&mut changed).poll(waker.as_ref()),
+            Poll::Ready(None)
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let counter = Counter::new();
+        let waker =
+            pin::pin!(unsafe { waker::<ValueGuard<WakePtr>>(&counter) });
+        let weak = pin::pin!(NotifyingRefGuard::new());
+        let mut changes = {
+            let strong = pin::pin!(NotifyingValueGuard::new(1));
+            weak.as_ref().register(strong.as_ref());
+
+            let mut changes = weak.as_ref().changes();
+            let mut changes = Pin::new(&mut changes);
+            assert_eq!(
+                changes.as_mut().poll_next(waker.as_ref()),
+                Poll::Pending
+            );
+
+            strong.as_ref().set(2);
+            strong.as_ref().set(3);
+            assert_eq!(
+                changes.as_mut().poll_next(waker.as_ref()),
+                Poll::Ready(Some(3))
+            );
+            assert_eq!(
+                changes.as_mut().poll_next(waker.as_ref()),
+                Poll::Pending
+            );
+
+            strong.as_ref().set(4);
+            assert_eq!(
+                changes.as_mut().poll_next(waker.as_ref()),
+                Poll::Ready(Some(4))
+            );
+            assert!(!changes.is_terminated());
+            weak.as_ref().changes()
+        };
+
+        assert_eq!(
+            Pin::new(&mut changes).poll_next(waker.as_ref()),
+            Poll::Ready(None)
+        );
+        assert!(changes.is_terminated());
+    }
+
+    #[cfg(all(feature = "atomics", not(loom)))]
+    #[test]
+    fn changed_atomic_waker() {
+        use futures_core::SyncWakePtr;
+
+        use crate::atomic_guard::AtomicValueGuard;
+
+        let counter = Counter::new();
+        let waker = pin::pin!(unsafe {
+            waker::<AtomicValueGuard<SyncWakePtr>>(&counter)
+        });
+        let weak = pin::pin!(NotifyingRefGuard::new());
+        let strong = pin::pin!(NotifyingValueGuard::new(1));
+        weak.as_ref().register(strong.as_ref());
+
+        let mut changed = weak.as_ref().changed();
+        let mut changed = Pin::new(&mut changed);
+        assert_eq!(changed.as_mut().poll(waker.as_ref()), Poll::Pending);
+
+        strong.as_ref().set(2);
+        assert_eq!(counter.count(), 1);
+        assert_eq!(changed.poll(waker.as_ref()), Poll::Ready(Some(2)));
+    }
+}
@@ -0,0 +1,122 @@
+use core::pin::{Pin, pin};
+
+use crate::guard::{RefGuard, ValueGuard};
+
+/// Creates a [`ValueGuard`] containing `value` and calls `f` with a pinned
+/// reference to it, along with a [`RefGuardFactory`] for creating
+/// [`RefGuard`]s registered to it.
+///
+/// The `ValueGuard` is owned by the stack frame of this function, and `f`
+/// only ever receives pinned references to it, so unlike a `ValueGuard`
+/// created with [`ValueGuard::new()`], safe code has no way to leak it using
+/// `mem::forget`. The same applies to every `RefGuard` created by the
+/// `RefGuardFactory`.
+///
+/// Note that this only holds for guards created through this function. A
+/// `RefGuard` created with [`RefGuard::new()`] and registered to the scoped
+/// `ValueGuard` must still uphold the safety requirements of `RefGuard`.
+///
+/// ```rust
+/// let value = lifetime_guard::scope(1, |value_guard, ref_guards| {
+///     ref_guards.with(|ref_guard| {
+///         assert_eq!(ref_guard.get(), Some(1));
+///         value_guard.set(2);
+///         ref_guard.get()
+///     })
+/// });
+/// assert_eq!(value, Some(2));
+/// ```
+#[inline]
+pub fn scope<T, R>(
+    value: T,
+    f: impl for<'a> FnOnce(Pin<&'a ValueGuard<T>>, RefGuardFactory<'a, T>) -> R,
+) -> R {
+    let value_guard = pin!(ValueGuard::new(value));
+    let value_guard = value_guard.into_ref();
+    f(value_guard, RefGuardFactory { value_guard })
+}
+
+/// Creates [`RefGuard`]s registered to the [`ValueGuard`] of a [`scope`].
+pub struct RefGuardFactory<'a, T> {
+    value_guard: Pin<&'a ValueGuard<T>>,
+}
+
+impl<T> Clone for RefGuardFactory<'_, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for RefGuardFactory<'_, T> {}
+
+impl<T> RefGuardFactory<'_, T> {
+    /// Creates a `RefGuard` registered to the `ValueGuard` of the scope and
+    /// calls `f` with a pinned reference to it.
+    ///
+    /// The `RefGuard` is owned by the stack frame of this function, so it
+    /// can't be leaked by safe code.
+    ///
+    /// Since a `ValueGuard`:`RefGuard` relationship is exclusive, this
+    /// invalidates any `RefGuard` registered to the `ValueGuard` of the scope,
+    /// including those created by enclosing calls to this method.
+    #[inline]
+    pub fn with<R>(
+        &self,
+        f: impl for<'b> FnOnce(Pin<&'b RefGuard<T>>) -> R,
+    ) -> R {
+        let ref_guard = pin!(RefGuard::new());
+        let ref_guard = ref_guard.into_ref();
+        ref_guard.register(self.value_guard);
+        f(ref_guard)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let value = scope(2, |value_guard, ref_guards| {
+            assert_eq!(value_guard.get(), 2);
+
+            let value = ref_guards.with(|ref_guard| {
+                assert_eq!(ref_guard.get(), Some(2));
+                value_guard.set(3);
+                ref_guard.get()
+            });
+
+            // the RefGuard was dropped, so the ValueGuard can be mutated
+            // freely
+            value_guard.set(4);
+            value
+        });
+        assert_eq!(value, Some(3));
+    }
+
+    #[test]
+    fn nested_ref_guards() {
+        scope(1, |_, ref_guards| {
+            ref_guards.with(|outer| {
+                assert_eq!(outer.get(), Some(1));
+                ref_guards.with(|inner| {
+                    // registering inner invalidates outer
+                    assert_eq!(outer.get(), None);
+                    assert_eq!(inner.get(), Some(1));
+                });
+                assert_eq!(outer.get(), None);
+            });
+        });
+    }
+
+    #[test]
+    fn outlives_value_guard() {
+        let ref_guard = pin!(RefGuard::new());
+        scope(5, |value_guard, _| {
+            ref_guard.as_ref().register(value_guard);
+            assert_eq!(ref_guard.get(), Some(5));
+        });
+        assert_eq!(ref_guard.get(), None);
+    }
+}
@@ -0,0 +1,314 @@
+use core::{marker::PhantomPinned, pin::Pin};
+
+use crate::guard::{RefGuard, ValueGuard};
+
+/// Strong guard for sharing a single interior mutable value with a
+/// [`SharedRefGuard`], which can write to the value as well as read it.
+///
+/// This behaves like a [`ValueGuard`]:[`RefGuard`] pair, and has the same
+/// invalidation on drop, but the value can be set from either side while
+/// both are alive, for example to hand a request to a reactor and receive
+/// its response in place.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct SharedGuard<T> {
+    guard: ValueGuard<T>,
+    _marker: PhantomPinned,
+}
+
+impl<T> SharedGuard<T> {
+    /// Creates a new `SharedGuard` containing `data`.
+    #[inline]
+    pub fn new(data: T) -> Self {
+        Self {
+            guard: ValueGuard::new(data),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Sets the internal value stored by `Self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`SharedRefGuard::with`].
+    #[inline]
+    pub fn set(&self, value: T) {
+        self.guard.set(value);
+    }
+
+    /// Replaces the internal value stored by `Self`, returning the old value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`SharedRefGuard::with`].
+    #[inline]
+    pub fn swap(&self, value: T) -> T {
+        self.guard.replace(value)
+    }
+
+    /// Calls `f` with a reference to the internal value stored by `Self`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        self.guard.with(f)
+    }
+
+    /// Returns `true` if a `SharedRefGuard` is currently registered to
+    /// `self`.
+    #[inline]
+    pub fn has_ref(&self) -> bool {
+        self.guard.has_ref()
+    }
+}
+
+impl<T: Copy> SharedGuard<T> {
+    /// Gets a copy of the value stored inside this `SharedGuard`.
+    #[inline]
+    pub fn get(&self) -> T {
+        self.guard.get()
+    }
+}
+
+impl<T: Copy + PartialEq> SharedGuard<T> {
+    /// Replaces the internal value stored by `Self` with `new` if it is equal
+    /// to `current`.
+    ///
+    /// Returns the previous value in `Ok` if it was replaced, and in `Err`
+    /// otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`SharedRefGuard::with`].
+    #[inline]
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T> {
+        compare_exchange(&self.guard, current, new)
+    }
+}
+
+/// Weak guard for acquiring read and write access to a `SharedGuard`'s
+/// value.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct SharedRefGuard<T> {
+    guard: RefGuard<T>,
+    _marker: PhantomPinned,
+}
+
+impl<T> SharedRefGuard<T> {
+    /// Creates a new `SharedRefGuard` with no reference to a `SharedGuard`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            guard: RefGuard::new(),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Binds a pinned `value_guard` to `self`.
+    ///
+    /// This behaves like [`RefGuard::register()`].
+    #[inline]
+    pub fn register<'a>(
+        self: Pin<&'a SharedRefGuard<T>>,
+        value_guard: Pin<&'a SharedGuard<T>>,
+    ) {
+        let guard = unsafe { self.map_unchecked(|this| &this.guard) };
+        guard
+            .register(unsafe { value_guard.map_unchecked(|this| &this.guard) });
+    }
+
+    /// Unbinds `self` from the `SharedGuard` it references, if any.
+    #[inline]
+    pub fn unregister(&self) {
+        self.guard.unregister();
+    }
+
+    /// Returns `true` if `self` currently references a `SharedGuard`.
+    #[inline]
+    pub fn is_registered(&self) -> bool {
+        self.guard.is_registered()
+    }
+
+    /// Calls `f` with a reference to the value stored inside the
+    /// `SharedGuard` this `SharedRefGuard` references, returning `None` if
+    /// there is no such `SharedGuard`.
+    #[inline]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.guard.with(f)
+    }
+
+    /// Sets the value stored inside the `SharedGuard` this `SharedRefGuard`
+    /// references, returning `value` in `Err` if there is no such
+    /// `SharedGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`SharedGuard::with`].
+    #[inline]
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.swap(value).map(drop)
+    }
+
+    /// Replaces the value stored inside the `SharedGuard` this
+    /// `SharedRefGuard` references, returning the old value, or `value` in
+    /// `Err` if there is no such `SharedGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`SharedGuard::with`].
+    #[inline]
+    pub fn swap(&self, value: T) -> Result<T, T> {
+        match self.guard.value_guard() {
+            Some(guard) => Ok(guard.replace(value)),
+            None => Err(value),
+        }
+    }
+}
+
+impl<T: Copy> SharedRefGuard<T> {
+    /// Gets a copy of the value stored inside the `SharedGuard` this
+    /// `SharedRefGuard` references.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.guard.get()
+    }
+}
+
+impl<T: Copy + PartialEq> SharedRefGuard<T> {
+    /// Replaces the value stored inside the `SharedGuard` this
+    /// `SharedRefGuard` references with `new` if it is equal to `current`,
+    /// as with [`SharedGuard::compare_exchange()`].
+    ///
+    /// Returns `None` if there is no such `SharedGuard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is currently borrowed by [`Self::with`] or
+    /// [`SharedGuard::with`].
+    #[inline]
+    pub fn compare_exchange(&self, current: T, new: T) -> Option<Result<T, T>> {
+        self.guard
+            .value_guard()
+            .map(|guard| compare_exchange(guard, current, new))
+    }
+}
+
+impl<T> Default for SharedRefGuard<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[inline]
+fn compare_exchange<T: Copy + PartialEq>(
+    guard: &ValueGuard<T>,
+    current: T,
+    new: T,
+) -> Result<T, T> {
+    let value = guard.get();
+    if value == current {
+        guard.set(new);
+        Ok(value)
+    } else {
+        Err(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::pin;
+
+    use super::*;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Status {
+        Pending,
+        Done(u8),
+    }
+
+    #[test]
+    fn write_back() {
+        let weak = pin::pin!(SharedRefGuard::new());
+        {
+            let strong = pin::pin!(SharedGuard::new(Status::Pending));
+            weak.as_ref().register(strong.as_ref());
+
+            assert_eq!(weak.as_ref().set(Status::Done(1)), Ok(()));
+            assert_eq!(strong.get(), Status::Done(1));
+
+            assert_eq!(strong.swap(Status::Pending), Status::Done(1));
+            assert_eq!(weak.swap(Status::Done(2)), Ok(Status::Pending));
+            assert_eq!(weak.get(), Some(Status::Done(2)));
+        }
+
+        assert!(!weak.is_registered());
+        assert_eq!(weak.as_ref().set(Status::Done(3)), Err(Status::Done(3)));
+        assert_eq!(weak.swap(Status::Done(4)), Err(Status::Done(4)));
+    }
+
+    #[test]
+    fn compare_exchange() {
+        let strong = pin::pin!(SharedGuard::new(Status::Pending));
+        let weak = pin::pin!(SharedRefGuard::new());
+        assert_eq!(
+            weak.compare_exchange(Status::Pending, Status::Done(0)),
+            None
+        );
+        weak.as_ref().register(strong.as_ref());
+
+        assert_eq!(
+            weak.compare_exchange(Status::Pending, Status::Done(1)),
+            Some(Ok(Status::Pending))
+        );
+        assert_eq!(
+            weak.compare_exchange(Status::Pending, Status::Done(2)),
+            Some(Err(Status::Done(1)))
+        );
+        assert_eq!(
+            strong.compare_exchange(Status::Done(1), Status::Pending),
+            Ok(Status::Done(1))
+        );
+        assert_eq!(
+            strong.compare_exchange(Status::Done(1), Status::Done(3)),
+            Err(Status::Pending)
+        );
+    }
+
+    #[test]
+    fn drop_ref_guard() {
+        let strong = pin::pin!(SharedGuard::new(1));
+        {
+            let weak = pin::pin!(SharedRefGuard::new());
+            weak.as_ref().register(strong.as_ref());
+            assert!(strong.has_ref());
+            weak.as_ref().set(2).unwrap();
+        }
+        assert!(!strong.has_ref());
+        assert_eq!(strong.get(), 2);
+    }
+}
@@ -0,0 +1,440 @@
+//! Thread safe guards that synchronize through per-guard tag bits rather than
+//! a global critical section.
+//!
+//! Each guard stores the pointer to its counterpart in an `AtomicPtr`, whose
+//! lowest bit doubles as a lock for that guard. A guard's counterpart can only
+//! be changed or dereferenced while holding its bit, and a guard's `Drop`
+//! implementation must acquire both bits to unlink itself, so neither side
+//! can be freed while the other is still reading through it.
+//!
+//! To avoid deadlocks, bits are always acquired in the order
+//! `SpinRefGuard` then `SpinValueGuard`. Operations that start from a
+//! `SpinValueGuard` may only *try* to acquire the bit of a
+//! `SpinRefGuard`, releasing their own bit and retrying on failure.
+//!
+//! These guards are *not* lock-free: a thread that is preempted while holding
+//! a bit makes every other thread touching either guard spin until it runs
+//! again. Critical sections are only a few instructions long, so this is
+//! cheap in practice, but an interrupt handler must never use a guard that
+//! the code it interrupted may be holding, since it would spin forever.
+//!
+//! A lock-free pair with the same semantics isn't possible. Guards live on
+//! the stack, so unlike hazard pointers, which defer freeing memory a reader
+//! may still access, a dropping guard has to wait for accesses to it that
+//! are in flight on other threads. Values of an arbitrary `T` also can't be
+//! replaced atomically, so `get` and `set` have to exclude each other. What
+//! these guards avoid is the global lock: two pairs never wait on each
+//! other.
+
+use core::{marker::PhantomPinned, pin::Pin, ptr};
+
+use crate::sync::{AtomicPtr, Ordering, UnsafeCell, spin_loop};
+
+/// Tag bit marking the guard holding an `AtomicPtr` as locked.
+const LOCKED: usize = 1;
+
+/// Attempts to acquire the tag bit of `ptr`, returning the untagged pointer
+/// on success.
+#[inline]
+fn try_lock<P>(ptr: &AtomicPtr<P>) -> Option<*mut P> {
+    // the load is only a guess, since it may observe a stale value, so the
+    // bit is only considered taken once `compare_exchange` observes it
+    let mut current =
+        ptr.load(Ordering::Relaxed).map_addr(|addr| addr & !LOCKED);
+    loop {
+        match ptr.compare_exchange(
+            current,
+            current.map_addr(|addr| addr | LOCKED),
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return Some(current),
+            Err(actual) if actual.addr() & LOCKED != 0 => return None,
+            Err(actual) => current = actual,
+        }
+    }
+}
+
+/// Spins until the tag bit of `ptr` is acquired, returning the untagged
+/// pointer.
+#[inline]
+fn lock<P>(ptr: &AtomicPtr<P>) -> *mut P {
+    loop {
+        if let Some(current) = try_lock(ptr) {
+            return current;
+        }
+        spin_loop();
+    }
+}
+
+/// Releases the tag bit of `ptr`, storing the untagged `value`.
+#[inline]
+fn unlock<P>(ptr: &AtomicPtr<P>, value: *mut P) {
+    ptr.store(value, Ordering::Release);
+}
+
+/// Strong guard for granting read access to a single interior mutable value to
+/// [`SpinRefGuard`](SpinRefGuard).
+///
+/// A `SpinValueGuard`:`SpinRefGuard` relationship is exclusive, and
+/// behaves similarly to a single `Arc` and `Weak` pair, but notably does not
+/// require heap allocation.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct SpinValueGuard<T> {
+    /// Contains the value being immutably accessed by `SpinRefGuard` and
+    /// mutably accessed by `Self`, only while holding the tag bit of
+    /// `ref_guard`.
+    data: UnsafeCell<T>,
+    /// A tagged pointer to a `SpinRefGuard` with read access to `data` to
+    /// invalidate that `SpinRefGuard` when `Self` is dropped.
+    ref_guard: AtomicPtr<SpinRefGuard<T>>,
+    _marker: PhantomPinned,
+}
+
+unsafe impl<T: Send> Send for SpinValueGuard<T> {}
+unsafe impl<T: Send> Sync for SpinValueGuard<T> {}
+
+impl<T> SpinValueGuard<T> {
+    /// Creates a new `SpinValueGuard` containing `data`.
+    #[inline]
+    pub fn new(data: T) -> Self {
+        Self {
+            data: UnsafeCell::new(data),
+            ref_guard: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Sets the internal value stored by `Self`.
+    #[inline]
+    pub fn set(&self, value: T) {
+        let ref_guard = lock(&self.ref_guard);
+        let old = self
+            .data
+            .with_mut(|data| unsafe { ptr::replace(data, value) });
+        unlock(&self.ref_guard, ref_guard);
+        // dropped outside of the lock in case `T::drop` accesses `self`
+        drop(old);
+    }
+}
+
+impl<T: Copy> SpinValueGuard<T> {
+    /// Gets a copy of the value stored inside this `SpinValueGuard`.
+    #[inline]
+    pub fn get(&self) -> T {
+        let ref_guard = lock(&self.ref_guard);
+        let value = self.data.with(|data| unsafe { *data });
+        unlock(&self.ref_guard, ref_guard);
+        value
+    }
+}
+
+impl<T> Drop for SpinValueGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        loop {
+            let ref_guard = lock(&self.ref_guard);
+            let Some(ref_guard_ref) = (unsafe { ref_guard.as_ref() }) else {
+                unlock(&self.ref_guard, ref_guard);
+                return;
+            };
+            // `ref_guard` can't finish dropping while we hold our own bit, so
+            // it is still valid, but we may only try to acquire its bit
+            if try_lock(&ref_guard_ref.value_guard).is_some() {
+                unlock(&ref_guard_ref.value_guard, ptr::null_mut());
+                unlock(&self.ref_guard, ptr::null_mut());
+                return;
+            }
+            unlock(&self.ref_guard, ref_guard);
+            spin_loop();
+        }
+    }
+}
+
+/// Weak guard for acquiring read only access to a `SpinValueGuard`'s
+/// value.
+///
+/// Provides [`SpinRefGuard::register()`](Self::register) to register a
+/// `SpinValueGuard` to `Self` and vice versa.
+///
+/// # Safety
+///
+/// This struct *must* not be leaked to the stack using `mem::forget` or any
+/// other mechanism that causes the contents of `Self` to be overwritten
+/// without `Drop::drop()` running.
+/// Doing so creates unsoundness that likely will lead to dereferencing a null
+/// pointer.
+///
+/// Note that it is sound to leak `Self` to the heap using methods including
+/// `Box::leak()` because heap allocated data will never be overwritten if it
+/// is never freed.
+pub struct SpinRefGuard<T> {
+    /// A tagged pointer to the `SpinValueGuard` `Self` has read access to.
+    value_guard: AtomicPtr<SpinValueGuard<T>>,
+    _marker: PhantomPinned,
+}
+
+unsafe impl<T: Send> Send for SpinRefGuard<T> {}
+unsafe impl<T: Send> Sync for SpinRefGuard<T> {}
+
+impl<T> SpinRefGuard<T> {
+    /// Creates a new `SpinRefGuard` with no reference to a
+    /// `SpinValueGuard`.
+    #[inline]
+    pub fn new() -> Self {
+        Self {
+            value_guard: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomPinned,
+        }
+    }
+
+    /// Invalidates the reference held by `value_guard` to `self`.
+    ///
+    /// Must be called while holding the tag bit of `self`, with `value_guard`
+    /// being the pointer stored by `self`.
+    #[inline]
+    fn unlink_value_guard(value_guard: *mut SpinValueGuard<T>) {
+        // `value_guard` can't finish dropping while we hold our own bit
+        if let Some(value_guard) = unsafe { value_guard.as_ref() } {
+            lock(&value_guard.ref_guard);
+            unlock(&value_guard.ref_guard, ptr::null_mut());
+        }
+    }
+
+    /// Binds a pinned `value_guard` to `self`.
+    ///
+    /// This means they will reference each other, and will invalidate their
+    /// references to each other when dropped.
+    ///
+    /// This method also invalidates the existing references held by the
+    /// now-replaced referencees of `self` and `value_guard` to avoid
+    /// dangling pointers.
+    #[inline]
+    pub fn register<'a>(
+        self: Pin<&'a SpinRefGuard<T>>,
+        value_guard: Pin<&'a SpinValueGuard<T>>,
+    ) {
+        let this: *mut Self = ptr::from_ref(self.get_ref()).cast_mut();
+        let value_guard_ptr: *mut SpinValueGuard<T> =
+            ptr::from_ref(value_guard.get_ref()).cast_mut();
+
+        loop {
+            let old_value_guard = lock(&self.value_guard);
+            if old_value_guard != value_guard_ptr {
+                Self::unlink_value_guard(old_value_guard);
+            }
+
+            let old_ref_guard = lock(&value_guard.ref_guard);
+            if old_ref_guard != this {
+                // `old_ref_guard` can't finish dropping while we hold the bit
+                // of `value_guard`
+                if let Some(old_ref_guard) = unsafe { old_ref_guard.as_ref() } {
+                    if try_lock(&old_ref_guard.value_guard).is_none() {
+                        unlock(
+                            &value_guard.ref_guard,
+                            ptr::from_ref(old_ref_guard).cast_mut(),
+                        );
+                        unlock(&self.value_guard, ptr::null_mut());
+                        spin_loop();
+                        continue;
+                    }
+                    unlock(&old_ref_guard.value_guard, ptr::null_mut());
+                }
+            }
+
+            unlock(&value_guard.ref_guard, this);
+            unlock(&self.value_guard, value_guard_ptr);
+            return;
+        }
+    }
+}
+
+impl<T: Copy> SpinRefGuard<T> {
+    /// Gets a copy of the value stored inside the `SpinValueGuard` this
+    /// `SpinRefGuard` references.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        let value_guard = lock(&self.value_guard);
+        // `value_guard` can't finish dropping while we hold our own bit
+        let value = unsafe { value_guard.as_ref() }.map(|guard| guard.get());
+        unlock(&self.value_guard, value_guard);
+        value
+    }
+}
+
+impl<T> Drop for SpinRefGuard<T> {
+    #[inline]
+    fn drop(&mut self) {
+        let value_guard = lock(&self.value_guard);
+        Self::unlink_value_guard(value_guard);
+        unlock(&self.value_guard, ptr::null_mut());
+    }
+}
+
+impl<T> Default for SpinRefGuard<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod test {
+    use core::{mem, pin};
+
+    extern crate alloc;
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let weak = pin::pin!(SpinRefGuard::new());
+        {
+            let strong = pin::pin!(SpinValueGuard::new(2));
+            weak.as_ref().register(strong.as_ref());
+
+            assert_eq!(strong.get(), 2);
+            assert_eq!(weak.get(), Some(2));
+
+            strong.as_ref().set(3);
+            assert_eq!(strong.get(), 3);
+            assert_eq!(weak.get(), Some(3));
+        }
+
+        assert_eq!(weak.get(), None);
+    }
+
+    #[test]
+    fn multiple_registrations() {
+        let weak1 = pin::pin!(SpinRefGuard::new());
+        let weak2 = pin::pin!(SpinRefGuard::new());
+        {
+            let strong = pin::pin!(SpinValueGuard::new(2));
+            weak1.as_ref().register(strong.as_ref());
+            assert_eq!(weak1.get(), Some(2));
+
+            // register next ptr, should invalidate previous weak ref (weak1)
+            weak2.as_ref().register(strong.as_ref());
+            assert_eq!(weak1.get(), None);
+            assert!(weak1.value_guard.load(Ordering::Relaxed).is_null());
+            assert_eq!(weak2.get(), Some(2));
+
+            // registering twice should be a no-op
+            weak2.as_ref().register(strong.as_ref());
+            assert_eq!(weak2.get(), Some(2));
+
+            strong.as_ref().set(4);
+            assert_eq!(weak2.get(), Some(4));
+
+            // moving weak2 to another guard should invalidate strong's ref
+            let other = pin::pin!(SpinValueGuard::new(5));
+            weak2.as_ref().register(other.as_ref());
+            assert!(strong.ref_guard.load(Ordering::Relaxed).is_null());
+            assert_eq!(weak2.get(), Some(5));
+        }
+
+        assert_eq!(weak1.get(), None);
+        assert_eq!(weak2.get(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn safe_leak() {
+        let strong = alloc::boxed::Box::pin(SpinValueGuard::new(10));
+        let weak = pin::pin!(SpinRefGuard::new());
+        weak.as_ref().register(strong.as_ref());
+
+        // strong is now a ValueGuard on the heap that will never be freed
+        // this is sound because it will never be overwritten
+        mem::forget(strong);
+
+        assert_eq!(weak.get(), Some(10));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_test {
+    extern crate alloc;
+    extern crate std;
+
+    use alloc::boxed::Box;
+    use loom::thread;
+
+    use super::*;
+
+    /// Spin loops can preempt each other indefinitely, so exploration has to
+    /// be bounded for the model to terminate.
+    fn model(f: impl Fn() + Sync + Send + 'static) {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(f);
+    }
+
+    fn pair(
+        value: i32,
+    ) -> (Pin<Box<SpinValueGuard<i32>>>, Pin<Box<SpinRefGuard<i32>>>) {
+        let strong = Box::pin(SpinValueGuard::new(value));
+        let weak = Box::pin(SpinRefGuard::new());
+        weak.as_ref().register(strong.as_ref());
+        (strong, weak)
+    }
+
+    #[test]
+    fn concurrent_drop() {
+        model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(strong));
+            drop(weak);
+            handle.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn get_while_value_guard_drops() {
+        model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(strong));
+            let value = weak.get();
+            assert!(value == Some(1) || value.is_none());
+            handle.join().unwrap();
+            assert_eq!(weak.get(), None);
+        });
+    }
+
+    #[test]
+    fn set_while_ref_guard_drops() {
+        model(|| {
+            let (strong, weak) = pair(1);
+            let handle = thread::spawn(move || drop(weak));
+            strong.as_ref().set(2);
+            handle.join().unwrap();
+            assert_eq!(strong.get(), 2);
+            assert!(strong.ref_guard.load(Ordering::Relaxed).is_null());
+        });
+    }
+
+    #[test]
+    fn register_while_value_guard_drops() {
+        model(|| {
+            let (strong, weak) = pair(1);
+            let other = Box::pin(SpinValueGuard::new(2));
+            let handle = thread::spawn(move || drop(strong));
+            weak.as_ref().register(other.as_ref());
+            handle.join().unwrap();
+            assert_eq!(weak.get(), Some(2));
+            drop(other);
+            assert_eq!(weak.get(), None);
+        });
+    }
+}
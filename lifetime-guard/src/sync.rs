@@ -0,0 +1,95 @@
+//! Synchronization primitives used by the thread safe guards, taken from the
+//! backend selected by feature flags and swapped for their
+//! [loom](https://github.com/tokio-rs/loom) equivalents under `cfg(loom)` so
+//! the guards can be model checked.
+
+#[cfg(all(feature = "atomics", not(loom)))]
+pub(crate) use core::cell::Cell;
+#[cfg(all(feature = "spin", not(feature = "portable-atomic"), not(loom)))]
+pub(crate) use core::sync::atomic::AtomicPtr;
+#[cfg(all(feature = "spin", not(loom)))]
+pub(crate) use core::{hint::spin_loop, sync::atomic::Ordering};
+#[cfg(all(feature = "spin", feature = "portable-atomic", not(loom)))]
+pub(crate) use portable_atomic::AtomicPtr;
+
+#[cfg(all(feature = "atomics", not(loom)))]
+pub(crate) use ::critical_section;
+
+#[cfg(all(feature = "atomics", loom))]
+pub(crate) use loom::cell::Cell;
+#[cfg(all(feature = "spin", loom))]
+pub(crate) use loom::{
+    cell::UnsafeCell,
+    hint::spin_loop,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// `core::cell::UnsafeCell` with the closure based api of
+/// `loom::cell::UnsafeCell`.
+#[cfg(all(feature = "spin", not(loom)))]
+#[derive(Debug)]
+pub(crate) struct UnsafeCell<T>(core::cell::UnsafeCell<T>);
+
+#[cfg(all(feature = "spin", not(loom)))]
+impl<T> UnsafeCell<T> {
+    #[inline]
+    pub(crate) const fn new(data: T) -> Self {
+        Self(core::cell::UnsafeCell::new(data))
+    }
+
+    #[inline]
+    pub(crate) fn with<R>(&self, f: impl FnOnce(*const T) -> R) -> R {
+        f(self.0.get())
+    }
+
+    #[inline]
+    pub(crate) fn with_mut<R>(&self, f: impl FnOnce(*mut T) -> R) -> R {
+        f(self.0.get())
+    }
+}
+
+/// Mock of the `critical_section` api backed by a single reentrant loom
+/// mutex.
+#[cfg(all(feature = "atomics", loom))]
+pub(crate) mod critical_section {
+    use core::{cell::Cell, marker::PhantomData};
+
+    loom::lazy_static! {
+        static ref LOCK: loom::sync::Mutex<()> = loom::sync::Mutex::new(());
+    }
+
+    loom::thread_local! {
+        static DEPTH: Cell<usize> = Cell::new(0);
+    }
+
+    #[derive(Clone, Copy)]
+    pub(crate) struct CriticalSection<'cs>(PhantomData<&'cs ()>);
+
+    pub(crate) struct Mutex<T>(T);
+
+    unsafe impl<T: Send> Sync for Mutex<T> {}
+
+    impl<T> Mutex<T> {
+        #[inline]
+        pub(crate) const fn new(value: T) -> Self {
+            Self(value)
+        }
+
+        #[inline]
+        pub(crate) fn borrow<'cs>(
+            &'cs self,
+            _cs: CriticalSection<'cs>,
+        ) -> &'cs T {
+            &self.0
+        }
+    }
+
+    pub(crate) fn with<R>(f: impl FnOnce(CriticalSection<'_>) -> R) -> R {
+        let guard = (DEPTH.with(Cell::get) == 0).then(|| LOCK.lock().unwrap());
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let result = f(CriticalSection(PhantomData));
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+        drop(guard);
+        result
+    }
+}
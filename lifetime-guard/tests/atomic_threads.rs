@@ -0,0 +1,106 @@
+#![cfg(all(feature = "atomics", not(loom)))]
+
+use std::{pin::pin, thread};
+
+use lifetime_guard::{
+    atomic_guard::{AtomicRefGuard, AtomicValueGuard},
+    atomic_shared_guard::AtomicSharedGuard,
+};
+
+#[test]
+fn register_from_another_thread() {
+    let strong = pin!(AtomicValueGuard::new(1));
+    let strong = strong.into_ref();
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            let weak = pin!(AtomicRefGuard::new());
+            weak.as_ref().register(strong);
+            assert_eq!(weak.get(), Some(1));
+            strong.set(2);
+            assert_eq!(weak.get(), Some(2));
+        });
+    });
+
+    // the RefGuard was dropped on the other thread
+    assert!(!strong.has_ref());
+    assert_eq!(strong.get(), 2);
+}
+
+#[test]
+fn set_from_many_threads() {
+    let strong = pin!(AtomicValueGuard::new(0));
+    let strong = strong.into_ref();
+    let weak = pin!(AtomicRefGuard::new());
+    let weak = weak.into_ref();
+    weak.register(strong);
+
+    thread::scope(|s| {
+        for value in 1..=4 {
+            s.spawn(move || strong.set(value));
+            s.spawn(move || {
+                let value = weak.get().unwrap();
+                assert!((0..=4).contains(&value));
+            });
+        }
+    });
+
+    assert_eq!(weak.get(), Some(strong.get()));
+}
+
+#[test]
+fn drop_value_guard_on_another_thread() {
+    let strong = Box::pin(AtomicValueGuard::new(String::from("sent")));
+    let weak = pin!(AtomicRefGuard::new());
+    weak.as_ref().register(strong.as_ref());
+
+    thread::scope(|s| {
+        s.spawn(|| {
+            let value = weak.with(|value| value.clone());
+            assert!(value.is_none() || value.as_deref() == Some("sent"));
+        });
+        s.spawn(move || drop(strong));
+    });
+
+    assert!(!weak.is_registered());
+    assert_eq!(weak.with(|value| value.len()), None);
+}
+
+#[test]
+fn drop_ref_guard_on_another_thread() {
+    let strong = pin!(AtomicValueGuard::new(1));
+    let weak = Box::pin(AtomicRefGuard::new());
+    weak.as_ref().register(strong.as_ref());
+
+    thread::spawn(move || {
+        assert!(weak.is_registered());
+        drop(weak);
+    })
+    .join()
+    .unwrap();
+
+    assert!(!strong.has_ref());
+}
+
+#[test]
+fn compare_exchange_from_many_threads() {
+    let counter = pin!(AtomicSharedGuard::new(0));
+    let counter = counter.into_ref();
+
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(move || {
+                for _ in 0..100 {
+                    let mut current = counter.get();
+                    while let Err(actual) =
+                        counter.compare_exchange(current, current + 1)
+                    {
+                        current = actual;
+                    }
+                }
+            });
+        }
+    });
+
+    assert_eq!(counter.get(), 400);
+}